@@ -1,36 +1,43 @@
-use crate::libfukushuu::shitsumon::{OptionPair, Question};
 use crate::{Choice, Error};
 use colored::Colorize;
 #[cfg(feature = "kittygfx")]
 use kitty_image::{Action, Command, WrappedCommand};
-use log::debug;
+use libfukushuu::audio::AudioPlayer;
+use libfukushuu::db::Answer;
+#[cfg(feature = "kittygfx")]
+use libfukushuu::db::Media;
+#[cfg(feature = "kittygfx")]
+use libfukushuu::shitsumon::Image;
+use libfukushuu::shitsumon::{finish_session, OptionPair, Question, SessionSummary};
+use log::{debug, error, warn};
 use rusqlite::Connection;
-use rusqlite::Result;
+use std::path::PathBuf;
+use std::time::Instant;
 use text_io::read;
 
 pub fn cli_loop(
     conn: &Connection,
-    questions: Vec<Question>,
+    mut questions: Vec<Question>,
     question_count: u32,
-    choices_count: u32,
+    session_id: i64,
+    player: &dyn AudioPlayer,
 ) -> Result<(), Error> {
     for idx in 1..questions.len() + 1 {
-        macro_rules! incr_and_print {
-            ($to_incr: expr) => {
-                let score = $to_incr.increment_score(&conn).unwrap();
-                println!(
-                    "{}",
-                    format!("Correct!: {} -> {}", score - 1, score).bright_green()
-                )
-            };
-        }
-        macro_rules! decr_and_print {
-            ($to_incr: expr) => {
-                let score = $to_incr.decrement_score(&conn).unwrap();
-                println!(
-                    "{}",
-                    format!("Incorrect!: {} -> {}", score + 1, score).bright_red()
-                )
+        macro_rules! record_and_print {
+            ($to_record: expr, $answer: expr, $chosen: expr, $response_time: expr) => {
+                let (before, after) =
+                    $to_record.record_answer(&conn, $answer, $chosen, $response_time)?;
+                if $answer == Answer::Correct {
+                    println!(
+                        "{}",
+                        format!("Correct!: {} -> {}", before, after).bright_green()
+                    )
+                } else {
+                    println!(
+                        "{}",
+                        format!("Incorrect!: {} -> {}", before, after).bright_red()
+                    )
+                }
             };
         }
 
@@ -47,6 +54,7 @@ pub fn cli_loop(
             .bold()
             .on_white()
         );
+        play(player, &questions[idx - 1].front_audio);
         let (options, correct) = questions[idx - 1].get_options_randomize();
 
         let indent = " ".repeat(leading.len());
@@ -56,10 +64,14 @@ pub fn cli_loop(
                 println!("{}", string);
             }
 
+            // Only shown in terminals with the kitty graphics protocol.
+            #[cfg(not(feature = "kittygfx"))]
+            let _ = img;
             #[cfg(feature = "kittygfx")]
-            if let Some(image_path) = img {
+            if let Some(image) = img {
+                let image_path = image_file(conn, image)?;
                 debug!("path at {image_path:?}");
-                let (width, height) = image::image_dimensions(image_path)?;
+                let (width, height) = image::image_dimensions(&image_path)?;
                 let x_offset = if str.is_some() {
                     10 * leading.len() as u32
                 } else {
@@ -79,26 +91,50 @@ pub fn cli_loop(
                     },
                 );
                 let command =
-                    WrappedCommand::new(Command::with_payload_from_path(action, image_path));
+                    WrappedCommand::new(Command::with_payload_from_path(action, &image_path));
                 println!("{command}");
                 print!("{}", "\n".repeat(height as usize / 20));
             }
         }
 
-        print!(
-            "{} ",
-            "Answer (1-4, q to quit prematurely and anything else if you don't know):".cyan()
+        // Questions from small pools can have fewer options than asked for.
+        let choices_count = options.len() as u32;
+        let replay = if questions[idx - 1].front_audio.is_some() {
+            ", r to replay the sound"
+        } else {
+            ""
+        };
+        let prompt = format!(
+            "Answer (1-{}{}, q to quit prematurely and anything else if you don't know):",
+            choices_count, replay
         );
-        let choice_string: String = read!("{}\n");
-        let choice = Choice::from_str(choices_count, choice_string.as_str());
+        let asked_at = Instant::now();
+        let choice = loop {
+            print!("{} ", prompt.cyan());
+            let choice_string: String = read!("{}\n");
+            match Choice::from_str(choices_count, choice_string.as_str()) {
+                Choice::Replay => play(player, &questions[idx - 1].front_audio),
+                choice => break choice,
+            }
+        };
+        let response_time = asked_at.elapsed();
         debug!("choice: {:?}", choice);
+        if choice != Choice::Quit {
+            play(player, &questions[idx - 1].back_audio);
+        }
 
         match choice {
             Choice::Option(num) => {
-                if num == correct {
-                    incr_and_print!(questions[idx - 1]);
+                let answer = if options
+                    .get(num)
+                    .is_some_and(|option| questions[idx - 1].is_correct(option))
+                {
+                    Answer::Correct
                 } else {
-                    decr_and_print!(questions[idx - 1]);
+                    Answer::Incorrect
+                };
+                record_and_print!(questions[idx - 1], answer, options.get(num), response_time);
+                if answer == Answer::Incorrect {
                     println!(
                         "{}",
                         format!("The correct choice was {:?}.", correct).green()
@@ -106,7 +142,7 @@ pub fn cli_loop(
                 }
             }
             Choice::DontKnow => {
-                decr_and_print!(questions[idx - 1]);
+                record_and_print!(questions[idx - 1], Answer::DontKnow, None, response_time);
                 println!(
                     "{}",
                     format!("The correct choice was {:?}.", correct).green()
@@ -114,9 +150,102 @@ pub fn cli_loop(
             }
             Choice::Quit => {
                 println!("{}", "Quitting Early!".cyan());
-                return Ok(());
+                break;
             }
+            Choice::Replay => unreachable!("replays are handled while reading the choice"),
         }
+        print_details(&questions[idx - 1], &indent);
+    }
+
+    match finish_session(conn, session_id) {
+        Ok(summary) => print_summary(&summary),
+        Err(err) => error!("Cannot finish session: {err}"),
     }
     Ok(())
 }
+
+/// Shows the reading, notes and example of an answered question, where the card has them.
+fn print_details(question: &Question, indent: &str) {
+    [
+        ("Reading", &question.reading),
+        ("Notes", &question.notes),
+        ("Example", &question.example),
+    ]
+    .into_iter()
+    .filter_map(|(label, text)| text.as_ref().map(|text| (label, text)))
+    .for_each(|(label, text)| println!("{}{} {}", indent, format!("{}:", label).cyan(), text));
+}
+
+/// Plays `audio` if the side has a sound. A sound that cannot be played is not worth
+/// stopping the quiz for.
+fn play(player: &dyn AudioPlayer, audio: &Option<PathBuf>) {
+    if let Some(path) = audio {
+        if let Err(err) = player.play(path) {
+            warn!("[Audio] {}", err);
+        }
+    }
+}
+
+/// Kitty reads images from files, so images stored in the database are written to the
+/// temp directory first, once per hash. A file already there is only reused if its contents
+/// still match the hash.
+#[cfg(feature = "kittygfx")]
+fn image_file(conn: &Connection, image: &Image) -> Result<PathBuf, Error> {
+    match image {
+        Image::File(path) => Ok(path.clone()),
+        Image::Media(hash) => {
+            let dir = std::env::temp_dir();
+            let path = dir.join(format!("nihongofukushuushiyou-{}", hash));
+            if let Ok(data) = std::fs::read(&path) {
+                if Media::hash_of(&data) == *hash {
+                    return Ok(path);
+                }
+                warn!(
+                    "Cached image {:?} does not match its hash, rewriting it",
+                    path
+                );
+            }
+            let media = Media::get(conn, hash)?;
+            // Written next to its final name and moved there, so no one sees half a file.
+            let mut file = tempfile::NamedTempFile::new_in(&dir)?;
+            std::io::Write::write_all(&mut file, &media.data)?;
+            file.persist(&path).map_err(|err| err.error)?;
+            Ok(path)
+        }
+    }
+}
+
+fn print_summary(summary: &SessionSummary) {
+    let session = &summary.session;
+    println!("{}", "==========> Summary <==========".cyan());
+    println!(
+        "{}",
+        format!(
+            "Answered {}/{}: {} correct, {} incorrect, {} not known ({:.0}% correct)",
+            summary.answered(),
+            session.question_count,
+            session.correct,
+            session.incorrect,
+            session.dont_know,
+            summary.accuracy() * 100.0
+        )
+        .bold()
+    );
+    let delta = format!("Score change: {:+}", session.score_delta);
+    if session.score_delta >= 0 {
+        println!("{}", delta.bright_green());
+    } else {
+        println!("{}", delta.bright_red());
+    }
+    if !summary.missed.is_empty() {
+        println!("{}", "Missed:".bright_red());
+        for card in &summary.missed {
+            let front = match (card.front.is_empty(), &card.front_media) {
+                (false, _) => card.front.clone(),
+                (true, Some(hash)) => format!("media:{}", hash),
+                (true, None) => card.front_image.display().to_string(),
+            };
+            println!("  {} → {}", front, card.back);
+        }
+    }
+}
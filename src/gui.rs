@@ -1,15 +1,15 @@
-use std::path::Path;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::libfukushuu::shitsumon::OptionPair;
-use crate::libfukushuu::shitsumon::Question;
 use crate::Error;
 use eframe::egui;
 use eframe::egui::Align;
+use eframe::egui::Color32;
 use eframe::egui::FontData;
 use eframe::egui::Frame;
 use eframe::egui::ImageSource;
-use eframe::egui::InnerResponse;
 use eframe::egui::Label;
 use eframe::egui::Response;
 use eframe::egui::RichText;
@@ -19,23 +19,39 @@ use eframe::egui::Ui;
 use eframe::egui::UiBuilder;
 use eframe::epaint::text::FontInsert;
 use eframe::epaint::text::InsertFontFamily;
-use log::debug;
+use libfukushuu::audio::AudioPlayer;
+use libfukushuu::db::{self, Answer};
+use libfukushuu::shitsumon::Question;
+use libfukushuu::shitsumon::SessionSummary;
+use libfukushuu::shitsumon::{Image, OptionPair};
+use libfukushuu::worker::{self, Request, Worker};
+use log::{debug, error, warn};
 use rusqlite::Connection;
-use rusqlite::OptionalExtension;
-use rusqlite::Result;
 
-struct GuiState<'a> {
-    conn: &'a Connection,
+struct GuiState {
+    /// Does all database work, so `update` never waits on it. Only `None` while dropping.
+    worker: Option<Worker>,
     questions: Vec<QuestionState>,
     question_count: u32,
-    choices_count: u32,
+    session_id: i64,
+    /// Set once every question has been answered.
+    summary: Option<SessionSummary>,
+    /// Whether the worker has been asked to finish the session and has not answered yet.
+    finishing: bool,
+    /// Images stored in the database, by hash, as they arrive from the worker.
+    media: HashMap<String, Arc<[u8]>>,
+    player: Box<dyn AudioPlayer>,
 
     current_question: usize,
 }
 struct QuestionState {
+    inner: Question,
     question: OptionPair,
     options: Vec<OptionPair>,
     correct_idx: usize,
+
+    shown_at: Option<Instant>,
+    chosen_idx: Option<usize>,
 }
 impl QuestionState {
     fn from_questions(from: Vec<Question>) -> Vec<Self> {
@@ -43,57 +59,103 @@ impl QuestionState {
             .map(|q| {
                 let (options, correct_idx) = q.get_options_randomize();
                 Self {
-                    question: q.front,
+                    question: q.front.clone(),
+                    inner: q,
                     options,
                     correct_idx,
+
+                    shown_at: None,
+                    chosen_idx: None,
                 }
             })
             .collect()
     }
 }
 
-impl<'a> GuiState<'a> {
+impl GuiState {
     fn new(
         ctx: &eframe::CreationContext,
-        conn: &'a Connection,
+        conn: Connection,
         questions: Vec<Question>,
         question_count: u32,
-        choices_count: u32,
+        session_id: i64,
+        player: Box<dyn AudioPlayer>,
     ) -> Self {
         add_fonts(ctx);
         egui_extras::install_image_loaders(&ctx.egui_ctx);
+        let egui_ctx = ctx.egui_ctx.clone();
 
-        Self {
-            conn,
+        let state = Self {
+            worker: Some(Worker::spawn(conn, move || egui_ctx.request_repaint())),
             questions: QuestionState::from_questions(questions),
             question_count,
-            choices_count,
+            session_id,
+            summary: None,
+            finishing: false,
+            media: HashMap::new(),
+            player,
 
             current_question: 0,
-        }
+        };
+        let mut hashes: Vec<&String> = state
+            .questions
+            .iter()
+            .filter_map(|q| match &q.question.1 {
+                Some(Image::Media(hash)) => Some(hash),
+                _ => None,
+            })
+            .collect();
+        hashes.sort();
+        hashes.dedup();
+        hashes
+            .into_iter()
+            .for_each(|hash| state.send(Request::LoadMedia(hash.clone())));
+        state
     }
 
-    fn draw_question_frame(&self, ui: &mut Ui, question_idx: u32) {
+    /// Draws a question and returns the index of the option that was clicked, if any.
+    fn draw_question_frame(&self, ui: &mut Ui, question_idx: u32) -> Option<usize> {
         let OptionPair(text, image) = &self.questions[question_idx as usize].question;
         let options = &self.questions[question_idx as usize].options;
+        let correct_idx = self.questions[question_idx as usize].correct_idx;
+        let chosen_idx = self.questions[question_idx as usize].chosen_idx;
+        let inner = &self.questions[question_idx as usize].inner;
+        let front_audio = &inner.front_audio;
         let mut results: Option<Vec<Response>> = None;
 
         ui.vertical(|ui| {
-            if let Some(img) = image {
-                if let Some(str) = img.as_os_str().to_str() {
-                    ui.image(format!("file://{}", str));
+            match image {
+                Some(Image::File(path)) => {
+                    if let Some(str) = path.as_os_str().to_str() {
+                        ui.image(format!("file://{}", str));
+                    }
                 }
+                Some(Image::Media(hash)) => match self.media.get(hash) {
+                    Some(bytes) => {
+                        ui.image(ImageSource::Bytes {
+                            uri: format!("bytes://{}", hash).into(),
+                            bytes: egui::load::Bytes::Shared(bytes.clone()),
+                        });
+                    }
+                    None => {
+                        ui.spinner();
+                    }
+                },
+                None => {}
             }
             if let Some(text) = text {
                 ui.label(RichText::new(text).size(40.0));
             }
+            if front_audio.is_some() && ui.button("🔊 Replay").clicked() {
+                self.play(front_audio);
+            }
             ui.separator();
             ui.horizontal_wrapped(|ui| {
                 _ = results.insert(
                     options
                         .iter()
                         .enumerate()
-                        .map(|(idx, OptionPair(opt, img_path))| {
+                        .map(|(idx, OptionPair(opt, _))| {
                             ui.scope_builder(
                                 UiBuilder::new()
                                     .id_salt(format!("option_{idx}"))
@@ -102,9 +164,14 @@ impl<'a> GuiState<'a> {
                                     let response = ui.response();
                                     let visuals = ui.style().interact(&response);
                                     let text_color = visuals.text_color();
+                                    let fill = match chosen_idx {
+                                        Some(_) if idx == correct_idx => Color32::DARK_GREEN,
+                                        Some(chosen) if idx == chosen => Color32::DARK_RED,
+                                        _ => visuals.bg_fill.gamma_multiply(0.3),
+                                    };
 
                                     Frame::canvas(ui.style())
-                                        .fill(visuals.bg_fill.gamma_multiply(0.3))
+                                        .fill(fill)
                                         .stroke(visuals.bg_stroke)
                                         .inner_margin(ui.spacing().menu_margin)
                                         .show(ui, |ui| {
@@ -128,8 +195,150 @@ impl<'a> GuiState<'a> {
                         .collect(),
                 );
             });
+            if chosen_idx.is_some() {
+                Self::draw_details(ui, inner);
+            }
+        });
+
+        if chosen_idx.is_some() {
+            return None;
+        }
+        results.and_then(|responses| responses.iter().position(Response::clicked))
+    }
+
+    fn answer(&mut self, question_idx: usize, chosen_idx: usize) {
+        let state = &mut self.questions[question_idx];
+        let answer = if state
+            .options
+            .get(chosen_idx)
+            .is_some_and(|option| state.inner.is_correct(option))
+        {
+            Answer::Correct
+        } else {
+            Answer::Incorrect
+        };
+        let response_time = state.shown_at.map_or(Duration::ZERO, |at| at.elapsed());
+        let request = Request::RecordAnswer {
+            question: state.inner.clone(),
+            answer,
+            chosen_option: state.options.get(chosen_idx).cloned(),
+            response_time,
+        };
+        state.chosen_idx = Some(chosen_idx);
+        let back_audio = state.inner.back_audio.clone();
+        self.send(request);
+        self.play(&back_audio);
+
+        if self.questions.iter().all(|q| q.chosen_idx.is_some()) {
+            self.finish_session();
+        }
+    }
+
+    /// Asks the worker to finish the session, unless it already has or is about to.
+    fn finish_session(&mut self) {
+        if self.summary.is_none() && !self.finishing {
+            self.finishing = true;
+            self.send(Request::FinishSession(self.session_id));
+        }
+    }
+
+    /// Plays `audio` if the side has a sound.
+    fn play(&self, audio: &Option<PathBuf>) {
+        if let Some(path) = audio {
+            if let Err(err) = self.player.play(path) {
+                warn!("[Audio] {}", err);
+            }
+        }
+    }
+
+    fn send(&self, request: Request) {
+        if !self
+            .worker
+            .as_ref()
+            .is_some_and(|worker| worker.send(request))
+        {
+            error!("Cannot reach the database worker!");
+        }
+    }
+
+    /// Applies whatever the worker has finished since the last frame.
+    fn poll_worker(&mut self) {
+        while let Some(response) = self.worker.as_ref().and_then(Worker::try_recv) {
+            match response {
+                worker::Response::AnswerRecorded {
+                    card_id,
+                    result: Ok((before, after)),
+                } => {
+                    debug!("Card {card_id}: {before} -> {after}");
+                    self.questions
+                        .iter_mut()
+                        .filter(|state| state.inner.card_id == card_id)
+                        .for_each(|state| state.inner.score = after);
+                }
+                worker::Response::AnswerRecorded {
+                    result: Err(err), ..
+                } => error!("Cannot record answer: {err}"),
+                worker::Response::SessionFinished(Ok(summary)) => {
+                    self.finishing = false;
+                    self.summary = Some(summary);
+                }
+                worker::Response::SessionFinished(Err(err)) => {
+                    self.finishing = false;
+                    error!("Cannot finish session: {err}")
+                }
+                worker::Response::MediaLoaded {
+                    hash,
+                    result: Ok(data),
+                } => {
+                    self.media.insert(hash, data.into());
+                }
+                worker::Response::MediaLoaded {
+                    hash,
+                    result: Err(err),
+                } => error!("Cannot load image {hash}: {err}"),
+                response => debug!("Ignoring {response:?}"),
+            }
+        }
+    }
+
+    /// The reading, notes and example of an answered question, where the card has them.
+    fn draw_details(ui: &mut Ui, question: &Question) {
+        [
+            ("Reading", &question.reading),
+            ("Notes", &question.notes),
+            ("Example", &question.example),
+        ]
+        .into_iter()
+        .filter_map(|(label, text)| text.as_ref().map(|text| (label, text)))
+        .for_each(|(label, text)| {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(RichText::new(label).strong().size(20.0));
+                ui.label(RichText::new(text).size(20.0));
+            });
         });
     }
+
+    fn draw_summary(ui: &mut Ui, summary: &SessionSummary) {
+        let session = &summary.session;
+        ui.heading(format!(
+            "{}/{} correct ({:.0}%)",
+            session.correct,
+            summary.answered(),
+            summary.accuracy() * 100.0
+        ));
+        let color = if session.score_delta >= 0 {
+            Color32::DARK_GREEN
+        } else {
+            Color32::DARK_RED
+        };
+        ui.label(RichText::new(format!("Score change: {:+}", session.score_delta)).color(color));
+        if !summary.missed.is_empty() {
+            ui.label(RichText::new("Missed:").color(Color32::DARK_RED));
+            for card in &summary.missed {
+                ui.label(format!("{} → {}", card.front, card.back));
+            }
+        }
+    }
 }
 
 fn add_fonts(ctx: &eframe::CreationContext) {
@@ -151,10 +360,28 @@ fn add_fonts(ctx: &eframe::CreationContext) {
     ));
 }
 
-impl eframe::App for GuiState<'_> {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+impl Drop for GuiState {
+    fn drop(&mut self) {
+        // Records when the window was closed if the quiz was left unfinished.
+        self.finish_session();
+        match self.worker.take().and_then(Worker::shutdown) {
+            Some(conn) => {
+                if let Err(err) = db::close_db(conn) {
+                    error!("Cannot close database: {err}");
+                }
+            }
+            None => error!("Database worker stopped unexpectedly!"),
+        }
+    }
+}
+
+impl eframe::App for GuiState {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_worker();
         let scroll = ScrollArea::horizontal().auto_shrink(false);
         let mut scroll_to = None;
+        let mut clicked = None;
+        let mut visible = vec![];
 
         egui::TopBottomPanel::bottom("question_dots").show(ctx, |ui| {
             ui.horizontal(|ui| {
@@ -168,6 +395,12 @@ impl eframe::App for GuiState<'_> {
             })
         });
 
+        if let Some(summary) = &self.summary {
+            egui::TopBottomPanel::top("summary").show(ctx, |ui| {
+                Self::draw_summary(ui, summary);
+            });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             scroll.show(ui, |ui| {
                 let height = ui.available_height();
@@ -180,10 +413,15 @@ impl eframe::App for GuiState<'_> {
                                     ui.set_width(width);
                                     ui.set_height(height);
 
-                                    self.draw_question_frame(ui, idx);
+                                    if let Some(chosen) = self.draw_question_frame(ui, idx) {
+                                        clicked = Some((idx as usize, chosen));
+                                    }
                                 })
                             })
                             .response;
+                        if ui.is_rect_visible(resp.rect) {
+                            visible.push(idx as usize);
+                        }
                         if let Some(scroll_dest) = scroll_to {
                             if scroll_dest == idx as usize {
                                 resp.scroll_to_me(Some(Align::Min));
@@ -194,14 +432,25 @@ impl eframe::App for GuiState<'_> {
                 });
             });
         });
+
+        for idx in visible {
+            if self.questions[idx].shown_at.is_none() {
+                self.questions[idx].shown_at = Some(Instant::now());
+                self.play(&self.questions[idx].inner.front_audio);
+            }
+        }
+        if let Some((question_idx, chosen_idx)) = clicked {
+            self.answer(question_idx, chosen_idx);
+        }
     }
 }
 
 pub fn init_gui(
-    conn: &Connection,
+    conn: Connection,
     questions: Vec<Question>,
     question_count: u32,
-    choices_count: u32,
+    session_id: i64,
+    player: Box<dyn AudioPlayer>,
 ) -> Result<(), Error> {
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -218,7 +467,8 @@ pub fn init_gui(
                 conn,
                 questions,
                 question_count,
-                choices_count,
+                session_id,
+                player,
             )))
         }),
     )?;
@@ -0,0 +1,94 @@
+#[cfg(feature = "audio")]
+use super::Error;
+use super::Result;
+use log::debug;
+#[cfg(feature = "audio")]
+use log::warn;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Plays the sound clips of cards. Starting a clip stops the one that was playing.
+pub trait AudioPlayer {
+    /// Starts playing the sound file at `path`, without waiting for it to end.
+    fn play(&self, path: &Path) -> Result<()>;
+    fn stop(&self);
+}
+
+/// Plays nothing, but remembers what it was asked to play. Used when the `audio` feature
+/// is off or there is no sound device, and in tests.
+#[derive(Debug, Default)]
+pub struct NullPlayer {
+    played: Mutex<Vec<PathBuf>>,
+}
+
+impl NullPlayer {
+    /// Every path passed to [`AudioPlayer::play`], oldest first.
+    pub fn played(&self) -> Vec<PathBuf> {
+        self.played.lock().unwrap().clone()
+    }
+}
+
+impl AudioPlayer for NullPlayer {
+    fn play(&self, path: &Path) -> Result<()> {
+        debug!("[Audio] Not playing {:?}", path);
+        self.played.lock().unwrap().push(path.to_path_buf());
+        Ok(())
+    }
+
+    fn stop(&self) {}
+}
+
+/// Plays through the default sound device.
+#[cfg(feature = "audio")]
+pub struct RodioPlayer {
+    // Sounds stop when the stream is dropped.
+    _stream: rodio::OutputStream,
+    handle: rodio::OutputStreamHandle,
+    sink: Mutex<Option<rodio::Sink>>,
+}
+
+#[cfg(feature = "audio")]
+impl RodioPlayer {
+    pub fn new() -> Result<RodioPlayer> {
+        let (stream, handle) =
+            rodio::OutputStream::try_default().map_err(|e| Error::Audio(e.to_string()))?;
+        Ok(RodioPlayer {
+            _stream: stream,
+            handle,
+            sink: Mutex::new(None),
+        })
+    }
+}
+
+#[cfg(feature = "audio")]
+impl AudioPlayer for RodioPlayer {
+    fn play(&self, path: &Path) -> Result<()> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| Error::Audio(format!("cannot open {:?}: {}", path, e)))?;
+        let source = rodio::Decoder::new(std::io::BufReader::new(file))
+            .map_err(|e| Error::Audio(format!("cannot decode {:?}: {}", path, e)))?;
+        let sink = rodio::Sink::try_new(&self.handle).map_err(|e| Error::Audio(e.to_string()))?;
+        sink.append(source);
+        debug!("[Audio] Playing {:?}", path);
+        // Dropping the previous sink stops it.
+        *self.sink.lock().unwrap() = Some(sink);
+        Ok(())
+    }
+
+    fn stop(&self) {
+        self.sink.lock().unwrap().take();
+    }
+}
+
+/// A [`RodioPlayer`] with the `audio` feature, or a [`NullPlayer`] without it or when the
+/// sound device cannot be opened.
+pub fn default_player() -> Box<dyn AudioPlayer> {
+    #[cfg(feature = "audio")]
+    match RodioPlayer::new() {
+        Ok(player) => return Box::new(player),
+        Err(err) => warn!("[Audio] {}, sounds will not be played", err),
+    }
+    #[cfg(not(feature = "audio"))]
+    debug!("[Audio] Built without the `audio` feature, sounds will not be played");
+    Box::new(NullPlayer::default())
+}
@@ -1,46 +1,245 @@
+use super::migrations;
+use super::normalize;
+use super::scheduler::{Progress, SchedulerKind};
+use super::shitsumon::Direction;
+use super::{Error, Result};
 use log::{debug, error, info, warn};
-use rusqlite::{params, Connection, DatabaseName, Result, Row};
-use std::path::PathBuf;
-use std::time::Instant;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, Type, ValueRef};
+use rusqlite::{ffi, named_params, params, Connection, OptionalExtension, Row, Statement};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+/// The profile used when none is given, and the one that owns the progress made before
+/// there were profiles.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// A group of pools, such as a textbook or one of its lessons. Categories can be nested.
 #[derive(Debug, Clone)]
 pub struct Category {
     pub name: String,
+    /// Scheduler for the category's cards, the default one if `None`.
+    pub scheduler: Option<SchedulerKind>,
+    /// The category this one is nested in, `None` for top-level categories.
+    pub parent_name: Option<String>,
+    /// Which way the category's cards are asked, forward if `None`.
+    pub direction: Option<Direction>,
 }
+/// Cards that are asked together. The incorrect options of a question are the backs of
+/// other cards in the same pool.
 #[derive(Debug, Clone)]
 pub struct Pool {
     pub id: i32,
     pub category_name: Option<String>,
 }
+/// A flashcard. Each side has text, an image or both, and optionally a sound. Unused
+/// fields are empty.
 #[derive(Debug, Clone)]
 pub struct Card {
+    /// `None` for cards not yet in the database. The database assigns the id on insert.
     pub id: Option<i32>,
     pub front: String,
     pub back: String,
     pub front_image: PathBuf,
     pub back_image: PathBuf,
-    pub score: Option<i32>,
     pub pool_id: Option<i32>,
     pub category_name: Option<String>,
+    /// [`Media`] hashes of the images, used instead of `front_image` and `back_image` when set.
+    pub front_media: Option<String>,
+    pub back_media: Option<String>,
+    /// Pronunciation clips, played by an [`AudioPlayer`](super::audio::AudioPlayer).
+    pub front_audio: PathBuf,
+    pub back_audio: PathBuf,
+    /// How the card is read, e.g. in kana.
+    pub reading: String,
+    /// A mnemonic or anything else worth knowing about the card.
+    pub notes: String,
+    /// An example sentence using the card.
+    pub example: String,
+}
+/// Someone studying with the database. Every profile has its own progress on each card.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    /// Seconds since the Unix epoch.
+    pub created_at: i64,
+}
+/// A label on cards, for quizzing on a part of a category.
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub name: String,
+}
+/// An image stored in the database, so decks keep their images when moved to another
+/// machine. Stored once per content, keyed by the SHA-256 of its bytes.
+#[derive(Debug, Clone)]
+pub struct Media {
+    /// Lowercase hex SHA-256 of `data`.
+    pub hash: String,
+    pub data: Vec<u8>,
+}
+/// A kind of [`Note`]: the fields its notes fill in and the cards made from them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteType {
+    pub name: String,
+    pub fields: Vec<String>,
+    /// One card is generated per template.
+    pub templates: Vec<CardTemplate>,
+}
+/// How a card of a [`NoteType`] is made. `{{field}}` in `front` and `back` stands for that
+/// field of the note.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardTemplate {
+    pub name: String,
+    pub front: String,
+    pub back: String,
+}
+/// One entry of a [`NoteType`], like a word with its kanji, reading and meaning. Its cards,
+/// one per template, are kept in sync with it by [`Note::regenerate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    /// `None` for notes not yet in the database.
+    pub id: Option<i32>,
+    pub note_type_name: String,
+    /// Where the generated cards go.
+    pub pool_id: i32,
+    pub fields: BTreeMap<String, String>,
+}
+/// Limits a query to cards with any of the `include` tags (or any card if
+/// there are none) and none of the `exclude` tags.
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+/// When a card should come back, as tracked by its [`Scheduler`](super::scheduler::Scheduler).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Schedule {
+    /// Seconds since the Unix epoch. `None` for cards that were never answered.
+    pub due: Option<i64>,
+    /// Days between the last answer and `due`.
+    pub interval: i32,
+    pub ease_factor: f64,
+    pub repetitions: i32,
+    /// FSRS memory state, `None` until the card is first answered with FSRS.
+    pub stability: Option<f64>,
+    pub difficulty: Option<f64>,
+    /// Seconds since the Unix epoch.
+    pub last_review: Option<i64>,
+}
+/// How a question was answered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Answer {
+    Correct,
+    Incorrect,
+    DontKnow,
+}
+/// One answer to a card, kept as its review history.
+#[derive(Debug, Clone)]
+pub struct Review {
+    pub id: Option<i32>,
+    pub card_id: i32,
+    pub profile_name: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub chosen_option: Option<String>,
+    pub answer: Answer,
+    pub response_time: Duration,
+    pub score_before: i32,
+    pub score_after: i32,
+    /// The quiz the answer was given in.
+    pub session_id: Option<i64>,
+}
+/// How the cards of a [`Session`] were picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionMode {
+    RandomPools,
+    DueToday,
+}
+/// One run of the quiz.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: Option<i64>,
+    pub profile_name: String,
+    /// Seconds since the Unix epoch.
+    pub started_at: i64,
+    /// Seconds since the Unix epoch, `None` while the quiz is still going.
+    pub ended_at: Option<i64>,
+    pub category_name: Option<String>,
+    pub mode: SessionMode,
+    pub question_count: u32,
+    pub choices_count: u32,
+    pub correct: u32,
+    pub incorrect: u32,
+    pub dont_know: u32,
+    /// Sum of the score changes of every answer.
+    pub score_delta: i32,
+}
+/// Something moved to the trash, along with everything in it. Trashed items are left out
+/// of quizzes and listings until the entry is restored, and deleted when the trash is
+/// emptied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trash {
+    pub id: i64,
+    pub item: TrashedItem,
+    /// Seconds since the Unix epoch.
+    pub deleted_at: i64,
+    /// Cards that went to the trash with this entry, the item itself if it is a card.
+    pub card_count: u32,
+}
+/// What a [`Trash`] entry was made for, as it was called when it was trashed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrashedItem {
+    Card(i32),
+    Pool(i32),
+    /// The category and every category nested in it.
+    Category(String),
 }
 
 impl Category {
-    pub fn create(connection: &Connection, name: String) -> Result<()> {
-        match connection.execute("INSERT INTO Category (name) VALUES (?1)", params![name]) {
+    /// Names of a category and everything nested in it, bound to `:categoryName`.
+    const SUBTREE_SQL: &'static str = "WITH RECURSIVE Subtree(name) AS (\
+        SELECT :categoryName \
+        UNION SELECT Category.name FROM Category JOIN Subtree ON Category.parentName = Subtree.name\
+        ) SELECT name FROM Subtree";
+
+    /// Inserts a category and returns it.
+    pub fn create(
+        connection: &Connection,
+        name: String,
+        scheduler: Option<SchedulerKind>,
+        parent_name: Option<String>,
+    ) -> Result<Category> {
+        let category = Category {
+            name,
+            scheduler,
+            parent_name,
+            direction: None,
+        };
+        Self::add(connection, category.clone())?;
+        Ok(category)
+    }
+
+    /// Inserts a category and returns its name.
+    pub fn add(connection: &Connection, src: Category) -> Result<String> {
+        match connection.execute(
+            "INSERT INTO Category (name, scheduler, parentName, direction) VALUES (?1, ?2, ?3, ?4)",
+            params![src.name, src.scheduler, src.parent_name, src.direction],
+        ) {
             Ok(_) => {
-                debug!("[DB] Created new Category '{}'", name);
-                Ok(())
+                debug!("[DB] Created new Category '{}'", src.name);
+                Ok(src.name)
             }
             Err(err) => {
                 error!("[DB] Error while creating new Category: {:?}", err);
-                Err(err)
+                Err(err.into())
             }
         }
     }
-
-    pub fn add(connection: &Connection, src: Category) -> Result<()> {
-        Self::create(connection, src.name)
-    }
+    /// Deletes the category for good, leaving its pools without one. See [`Category::trash`]
+    /// for a deletion that can be undone.
     pub fn delete(connection: &Connection, name: String) -> Result<()> {
         match connection.execute("DELETE FROM Category WHERE name = ?1", params![name]) {
             Ok(_) => {
@@ -49,47 +248,215 @@ impl Category {
             }
             Err(err) => {
                 error!("[DB] Error while deleting Category: {:?}", err);
-                Err(err)
+                Err(err.into())
             }
         }
     }
 
+    /// Moves the category to the trash with its nested categories, their pools and cards.
+    /// Returns the id of the [`Trash`] entry.
+    pub fn trash(connection: &Connection, name: &str) -> Result<i64> {
+        Trash::create(connection, TrashedItem::Category(name.to_string()))
+    }
+
+    /// Whether the category is in the trash, by itself or with a category it is nested in.
+    pub fn in_trash(connection: &Connection, name: &str) -> Result<bool> {
+        Ok(connection.query_row(
+            "SELECT trashId IS NOT NULL FROM Category WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Category> {
+        Ok(Category {
+            name: row.get(0)?,
+            scheduler: row.get(1)?,
+            parent_name: row.get(2)?,
+            direction: row.get(3)?,
+        })
+    }
+
     pub fn get_all(connection: &Connection) -> Result<Vec<Category>> {
-        let mut statement = connection.prepare("SELECT * FROM Category")?;
-        let rows = statement.query_map([], |row| Ok(Category { name: row.get(0)? }))?;
+        let mut statement = connection.prepare("SELECT * FROM Category WHERE trashId IS NULL")?;
+        let rows = statement.query_map([], Self::from_row)?;
 
-        rows.collect()
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
     }
 
     pub fn get_one(connection: &Connection, name: &str) -> Result<Category> {
         let mut statement =
             connection.prepare("SELECT * FROM Category WHERE name = :name LIMIT 1")?;
-        let row = statement.query_row(&[(":name", &name)], |row| row.get(0))?;
+        let row = statement.query_row(&[(":name", &name)], Self::from_row)?;
 
-        Ok(Category { name: row })
+        Ok(row)
     }
-}
-impl Pool {
-    pub fn create(connection: &Connection, id: i32, category_name: Option<String>) -> Result<()> {
-        let actual_name = category_name.unwrap_or_default();
+
+    pub fn set_scheduler(
+        connection: &Connection,
+        name: &str,
+        scheduler: Option<SchedulerKind>,
+    ) -> Result<()> {
         match connection.execute(
-            "INSERT INTO Pool (id, categoryName) VALUES (?1, ?2)",
-            params![id, actual_name],
+            "UPDATE Category SET scheduler = ?2 WHERE name = ?1",
+            params![name, scheduler],
         ) {
             Ok(_) => {
-                debug!("[DB] Created new Pool {} with name '{}'", id, actual_name);
+                debug!(
+                    "[DB] Set scheduler of Category '{}' to {:?}",
+                    name, scheduler
+                );
                 Ok(())
             }
             Err(err) => {
-                error!("[DB] Error while creating new Pool {}: {:?}", id, err);
-                Err(err)
+                error!("[DB] Failed to set scheduler for Category '{}'.", name);
+                Err(err.into())
+            }
+        }
+    }
+
+    pub fn set_direction(
+        connection: &Connection,
+        name: &str,
+        direction: Option<Direction>,
+    ) -> Result<()> {
+        match connection.execute(
+            "UPDATE Category SET direction = ?2 WHERE name = ?1",
+            params![name, direction],
+        ) {
+            Ok(_) => {
+                debug!(
+                    "[DB] Set direction of Category '{}' to {:?}",
+                    name, direction
+                );
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Failed to set direction for Category '{}'.", name);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Top-level categories, ones without a parent.
+    pub fn get_roots(connection: &Connection) -> Result<Vec<Category>> {
+        let mut statement = connection
+            .prepare("SELECT * FROM Category WHERE parentName IS NULL AND trashId IS NULL")?;
+        let rows = statement.query_map([], Self::from_row)?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn get_children(connection: &Connection, name: &str) -> Result<Vec<Category>> {
+        let mut statement = connection
+            .prepare("SELECT * FROM Category WHERE parentName = :name AND trashId IS NULL")?;
+        let rows = statement.query_map(&[(":name", &name)], Self::from_row)?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The category and every category nested in it, at any depth.
+    pub fn get_subtree(connection: &Connection, name: &str) -> Result<Vec<Category>> {
+        let mut statement = connection.prepare(&format!(
+            "SELECT * FROM Category WHERE name IN ({}) AND trashId IS NULL",
+            Self::SUBTREE_SQL
+        ))?;
+        let rows = statement.query_map(&[(":categoryName", &name)], Self::from_row)?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Moves a category under `parent_name`, or to the top level if `None`.
+    /// Fails if that would nest the category inside itself.
+    pub fn set_parent(
+        connection: &Connection,
+        name: &str,
+        parent_name: Option<&str>,
+    ) -> Result<()> {
+        if let Some(parent) = parent_name {
+            let subtree = Self::get_subtree(connection, name)?;
+            if subtree.iter().any(|category| category.name == parent) {
+                error!(
+                    "[DB] Cannot move Category '{}' into '{}', which is nested in it.",
+                    name, parent
+                );
+                return Err(rusqlite::Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_CONSTRAINT),
+                    Some(format!("Category '{parent}' is nested in '{name}'")),
+                )
+                .into());
+            }
+        }
+        match connection.execute(
+            "UPDATE Category SET parentName = ?2 WHERE name = ?1",
+            params![name, parent_name],
+        ) {
+            Ok(_) => {
+                debug!("[DB] Moved Category '{}' into {:?}", name, parent_name);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Failed to move Category '{}'.", name);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Renames a category. Its pools, cards and nested categories follow along.
+    pub fn rename(connection: &Connection, name: &str, new_name: &str) -> Result<()> {
+        match connection.execute(
+            "UPDATE Category SET name = ?2 WHERE name = ?1",
+            params![name, new_name],
+        ) {
+            Ok(_) => {
+                debug!("[DB] Renamed Category '{}' to '{}'", name, new_name);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while renaming Category '{}': {:?}", name, err);
+                Err(err.into())
             }
         }
     }
+}
+impl Pool {
+    /// Inserts a pool and returns it. SQLite picks the id if `id` is `None`.
+    pub fn create(
+        connection: &Connection,
+        id: Option<i32>,
+        category_name: Option<String>,
+    ) -> Result<Pool> {
+        let id = Self::insert(connection, id, &category_name)?;
+        Ok(Pool { id, category_name })
+    }
+
+    /// Inserts a pool with its id and returns the id.
+    pub fn add(connection: &Connection, src: Pool) -> Result<i32> {
+        Self::insert(connection, Some(src.id), &src.category_name)
+    }
 
-    pub fn add(connection: &Connection, src: Pool) -> Result<()> {
-        Self::create(connection, src.id, src.category_name)
+    fn insert(
+        connection: &Connection,
+        id: Option<i32>,
+        category_name: &Option<String>,
+    ) -> Result<i32> {
+        match connection.execute(
+            "INSERT INTO Pool (id, categoryName) VALUES (?1, ?2)",
+            params![id, category_name],
+        ) {
+            Ok(_) => {
+                let id = connection.last_insert_rowid() as i32;
+                debug!("[DB] Created new Pool {} with name {:?}", id, category_name);
+                Ok(id)
+            }
+            Err(err) => {
+                error!("[DB] Error while creating new Pool {:?}: {:?}", id, err);
+                Err(err.into())
+            }
+        }
     }
+    /// Deletes the pool for good, leaving its cards without one. See [`Pool::trash`] for a
+    /// deletion that can be undone.
     pub fn delete(connection: &Connection, id: i32) -> Result<()> {
         match connection.execute("DELETE FROM Pool WHERE id = ?1", params![id]) {
             Ok(_) => {
@@ -98,13 +465,69 @@ impl Pool {
             }
             Err(err) => {
                 error!("[DB] Error while deleting Pool {}: {:?}", id, err);
-                Err(err)
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Moves the pool to the trash with its cards. Returns the id of the [`Trash`] entry.
+    pub fn trash(connection: &Connection, id: i32) -> Result<i64> {
+        Trash::create(connection, TrashedItem::Pool(id))
+    }
+
+    /// Whether the pool is in the trash, by itself or with its category.
+    pub fn in_trash(connection: &Connection, id: i32) -> Result<bool> {
+        Ok(connection.query_row(
+            "SELECT trashId IS NOT NULL FROM Pool WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Changes a pool's id. Its cards follow along.
+    pub fn rename(connection: &Connection, id: i32, new_id: i32) -> Result<()> {
+        match connection.execute("UPDATE Pool SET id = ?2 WHERE id = ?1", params![id, new_id]) {
+            Ok(_) => {
+                debug!("[DB] Renamed Pool {} to {}", id, new_id);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while renaming Pool {}: {:?}", id, err);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Moves a pool and all of its cards to another category.
+    pub fn reassign_category(
+        connection: &Connection,
+        id: i32,
+        category_name: Option<String>,
+    ) -> Result<()> {
+        let result = atomically(connection, || {
+            connection.execute(
+                "UPDATE Pool SET categoryName = ?2 WHERE id = ?1",
+                params![id, category_name],
+            )?;
+            connection.execute(
+                "UPDATE Card SET categoryName = ?2 WHERE poolId = ?1",
+                params![id, category_name],
+            )
+        });
+        match result {
+            Ok(_) => {
+                debug!("[DB] Moved Pool {} to Category {:?}", id, category_name);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while moving Pool {}: {:?}", id, err);
+                Err(err.into())
             }
         }
     }
 
     pub fn get_all(connection: &Connection) -> Result<Vec<Pool>> {
-        let mut statement = connection.prepare("SELECT * FROM Pool")?;
+        let mut statement = connection.prepare("SELECT * FROM Pool WHERE trashId IS NULL")?;
         let rows = statement.query_map([], |row| {
             Ok(Pool {
                 id: row.get(0)?,
@@ -112,7 +535,7 @@ impl Pool {
             })
         })?;
 
-        rows.collect()
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
     }
     pub fn get_by_id(connection: &Connection, id: i32) -> Result<Pool> {
         let mut statement = connection.prepare("SELECT * FROM Pool WHERE id = :id LIMIT 1")?;
@@ -125,11 +548,30 @@ impl Pool {
 
         Ok(row)
     }
+    /// Pools in the category and every category nested in it.
+    pub fn get_all_in_category_tree(
+        connection: &Connection,
+        category_name: &str,
+    ) -> Result<Vec<Pool>> {
+        let mut statement = connection.prepare(&format!(
+            "SELECT * FROM Pool WHERE categoryName IN ({}) AND trashId IS NULL",
+            Category::SUBTREE_SQL
+        ))?;
+        let rows = statement.query_map(&[(":categoryName", &category_name)], |row| {
+            Ok(Pool {
+                id: row.get(0)?,
+                category_name: row.get(1)?,
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
     pub fn get_all_in_category(
         connection: &Connection,
         category_name: &String,
     ) -> Result<Vec<Pool>> {
-        let mut statement = connection.prepare("SELECT * FROM Pool WHERE categoryName = :name")?;
+        let mut statement = connection
+            .prepare("SELECT * FROM Pool WHERE categoryName = :name AND trashId IS NULL")?;
         let rows = statement.query_map(&[(":name", &category_name)], |row| {
             Ok(Pool {
                 id: row.get(0)?,
@@ -137,78 +579,88 @@ impl Pool {
             })
         })?;
 
-        rows.collect()
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
     }
 }
-impl Card {
-    fn insert(
-        connection: &Connection,
-        id: i32,
-        front: String,
-        back: String,
-        front_image: PathBuf,
-        back_image: PathBuf,
-        score: i32,
-        pool_id: i32,
-        category_name: String,
-    ) -> Result<()> {
-        let front_image_resolved = front_image
-            .into_os_string()
-            .into_string()
-            .unwrap_or_default();
-        let back_image_resolved = back_image
-            .into_os_string()
-            .into_string()
-            .unwrap_or_default();
-        match connection.execute(
+/// Inserts cards through a single prepared statement, for importers adding many at once.
+pub struct CardInserter<'conn> {
+    connection: &'conn Connection,
+    statement: Statement<'conn>,
+}
+impl<'conn> CardInserter<'conn> {
+    pub fn new(connection: &'conn Connection) -> Result<Self> {
+        let statement = connection.prepare(
             "INSERT INTO \
-            Card (id, front, back, frontImage, backImage, score, poolId, categoryName) \
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![
-                id,
-                front,
-                back,
-                front_image_resolved,
-                back_image_resolved,
-                score,
-                pool_id,
-                category_name
-            ],
-        ) {
+            Card (id, front, back, frontImage, backImage, poolId, categoryName, frontMedia, backMedia, \
+            frontAudio, backAudio, reading, notes, example, frontFolded, backFolded) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        )?;
+        Ok(CardInserter {
+            connection,
+            statement,
+        })
+    }
+
+    /// Inserts the card and returns its id, which SQLite picks if the card has none.
+    pub fn insert(&mut self, src: &Card) -> Result<i32> {
+        match self.statement.execute(params![
+            src.id,
+            src.front,
+            src.back,
+            src.front_image.to_str().unwrap_or_default(),
+            src.back_image.to_str().unwrap_or_default(),
+            src.pool_id,
+            src.category_name,
+            src.front_media,
+            src.back_media,
+            src.front_audio.to_str().unwrap_or_default(),
+            src.back_audio.to_str().unwrap_or_default(),
+            src.reading,
+            src.notes,
+            src.example,
+            normalize::fold(&src.front),
+            normalize::fold(&src.back)
+        ]) {
             Ok(_) => {
+                let id = self.connection.last_insert_rowid() as i32;
                 debug!(
-                    "[DB] Created new Card {} in Pool {} in Category {}",
-                    id, pool_id, category_name
+                    "[DB] Created new Card {} in Pool {:?} in Category {:?}",
+                    id, src.pool_id, src.category_name
                 );
-                Ok(())
+                Ok(id)
             }
             Err(err) => {
-                error!("[DB] Error while creating new Card {}: {:?}", id, err);
-                Err(err)
+                error!("[DB] Error while creating new Card {:?}: {:?}", src.id, err);
+                Err(err.into())
             }
         }
     }
+}
 
-    pub fn add(connection: &Connection, src: Card) -> Result<()> {
-        let id = Self::latest_id(connection).unwrap_or(-1) + 1;
-        Self::insert(
-            connection,
-            src.id.unwrap_or(id),
-            src.front,
-            src.back,
-            src.front_image,
-            src.back_image,
-            src.score.unwrap_or(0),
-            src.pool_id.unwrap(),
-            src.category_name.unwrap(),
-        )
+impl Card {
+    /// Inserts the card and returns its id, which SQLite picks if the card has none.
+    pub fn add(connection: &Connection, src: Card) -> Result<i32> {
+        CardInserter::new(connection)?.insert(&src)
+    }
+
+    /// Inserts the card and returns it with its id.
+    pub fn create(connection: &Connection, src: Card) -> Result<Card> {
+        let id = Self::add(connection, src.clone())?;
+        Ok(Card {
+            id: Some(id),
+            ..src
+        })
     }
 
-    fn latest_id(connection: &Connection) -> Result<i32> {
-        let mut statement = connection.prepare("SELECT id FROM Card ORDER BY id DESC LIMIT 1")?;
-        statement.query_row([], |row| row.get(0))
+    /// Inserts all `cards` or, if one of them fails, none. Returns their ids in order.
+    pub fn add_all(connection: &Connection, cards: &[Card]) -> Result<Vec<i32>> {
+        atomically(connection, || {
+            let mut inserter = CardInserter::new(connection)?;
+            cards.iter().map(|card| inserter.insert(card)).collect()
+        })
     }
 
+    /// Deletes the card for good. See [`Card::trash`] for a deletion that can be undone.
     pub fn delete(connection: &Connection, id: i32) -> Result<()> {
         match connection.execute("DELETE FROM Card WHERE id = ?1", params![id]) {
             Ok(_) => {
@@ -217,29 +669,42 @@ impl Card {
             }
             Err(err) => {
                 error!("[DB] Error while deleting Card {}: {:?}", id, err);
-                Err(err)
+                Err(err.into())
             }
         }
     }
 
-    fn from_row(row: &Row) -> Result<Card> {
+    /// Moves the card to the trash. Returns the id of the [`Trash`] entry.
+    pub fn trash(connection: &Connection, id: i32) -> Result<i64> {
+        Trash::create(connection, TrashedItem::Card(id))
+    }
+
+    /// Reads the columns by name, as the ones added by migrations are not in the same order
+    /// in every database.
+    fn from_row(row: &Row) -> rusqlite::Result<Card> {
         Ok(Card {
-            id: row.get(0)?,
-            front: row.get(1)?,
-            back: row.get(2)?,
-            front_image: PathBuf::from(row.get::<usize, String>(3)?),
-            back_image: PathBuf::from(row.get::<usize, String>(4)?),
-            score: row.get(5)?,
-            pool_id: row.get(6)?,
-            category_name: row.get(7)?,
+            id: row.get("id")?,
+            front: row.get("front")?,
+            back: row.get("back")?,
+            front_image: PathBuf::from(row.get::<_, String>("frontImage")?),
+            back_image: PathBuf::from(row.get::<_, String>("backImage")?),
+            pool_id: row.get("poolId")?,
+            category_name: row.get("categoryName")?,
+            front_media: row.get("frontMedia")?,
+            back_media: row.get("backMedia")?,
+            front_audio: PathBuf::from(row.get::<_, String>("frontAudio")?),
+            back_audio: PathBuf::from(row.get::<_, String>("backAudio")?),
+            reading: row.get("reading")?,
+            notes: row.get("notes")?,
+            example: row.get("example")?,
         })
     }
 
     pub fn get_all(connection: &Connection) -> Result<Vec<Card>> {
-        let mut statement = connection.prepare("SELECT * FROM Card")?;
+        let mut statement = connection.prepare("SELECT * FROM Card WHERE trashId IS NULL")?;
         let rows = statement.query_map([], Self::from_row)?;
 
-        rows.collect()
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
     }
 
     pub fn get_by_id(connection: &Connection, id: i32) -> Result<Card> {
@@ -250,76 +715,1362 @@ impl Card {
     }
 
     pub fn get_in_pool(connection: &Connection, pool_id: i32) -> Result<Vec<Card>> {
-        let mut statement = connection.prepare("SELECT * FROM Card WHERE poolId = :poolId")?;
+        let mut statement =
+            connection.prepare("SELECT * FROM Card WHERE poolId = :poolId AND trashId IS NULL")?;
         let rows = statement.query_map(&[(":poolId", &pool_id)], Self::from_row)?;
 
-        rows.collect()
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
     }
 
     pub fn get_in_category(connection: &Connection, category_name: String) -> Result<Vec<Card>> {
-        let mut statement =
-            connection.prepare("SELECT * FROM Card WHERE categoryName = :categoryName")?;
+        let mut statement = connection
+            .prepare("SELECT * FROM Card WHERE categoryName = :categoryName AND trashId IS NULL")?;
         let rows = statement.query_map(&[(":categoryName", &category_name)], |row| {
             Self::from_row(row)
         })?;
 
-        rows.collect()
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
     }
 
-    pub fn change_score(connection: &Connection, id: i32, score: i32) -> Result<i32> {
+    /// Replaces the contents of a card, keeping its id, progress and pool.
+    pub fn update(connection: &Connection, id: i32, src: Card) -> Result<()> {
+        let front_image_resolved = src
+            .front_image
+            .into_os_string()
+            .into_string()
+            .unwrap_or_default();
+        let back_image_resolved = src
+            .back_image
+            .into_os_string()
+            .into_string()
+            .unwrap_or_default();
         match connection.execute(
-            "UPDATE Card SET score = ?2 WHERE id = ?1",
-            params![id, score],
+            "UPDATE Card SET front = ?2, back = ?3, frontImage = ?4, backImage = ?5, \
+            frontMedia = ?6, backMedia = ?7, frontAudio = ?8, backAudio = ?9, \
+            reading = ?10, notes = ?11, example = ?12, frontFolded = ?13, backFolded = ?14 \
+            WHERE id = ?1",
+            params![
+                id,
+                src.front,
+                src.back,
+                front_image_resolved,
+                back_image_resolved,
+                src.front_media,
+                src.back_media,
+                src.front_audio.to_str().unwrap_or_default(),
+                src.back_audio.to_str().unwrap_or_default(),
+                src.reading,
+                src.notes,
+                src.example,
+                normalize::fold(&src.front),
+                normalize::fold(&src.back)
+            ],
         ) {
-            Ok(_) => Ok(score),
+            Ok(_) => {
+                debug!("[DB] Updated Card {}", id);
+                Ok(())
+            }
             Err(err) => {
-                error!("[DB] Failed to update score for ID {}.", id);
-                Err(err)
+                error!("[DB] Error while updating Card {}: {:?}", id, err);
+                Err(err.into())
             }
         }
     }
-    pub fn get_score(connection: &Connection, id: i32) -> Result<Option<i32>> {
-        let card = Card::get_by_id(connection, id)?;
-        Ok(card.score)
+
+    /// Moves a card to another pool, and with it to that pool's category.
+    pub fn move_to_pool(connection: &Connection, id: i32, pool_id: i32) -> Result<()> {
+        match connection.execute(
+            "UPDATE Card SET poolId = ?2, categoryName = (SELECT categoryName FROM Pool WHERE id = ?2) \
+            WHERE id = ?1",
+            params![id, pool_id],
+        ) {
+            Ok(_) => {
+                debug!("[DB] Moved Card {} to Pool {}", id, pool_id);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while moving Card {}: {:?}", id, err);
+                Err(err.into())
+            }
+        }
     }
-}
-pub fn create_or_open(src: PathBuf) -> Result<Connection> {
-    if src.exists() {
-        info!("[DB] Opening existing Database");
-        open_db(src)
-    } else {
-        info!("[DB] Creating new Database");
-        create_db(src)
+
+    pub fn get_in_pool_filtered(
+        connection: &Connection,
+        pool_id: i32,
+        filter: &TagFilter,
+    ) -> Result<Vec<Card>> {
+        let mut statement = connection.prepare(&format!(
+            "SELECT * FROM Card WHERE poolId = :poolId AND trashId IS NULL AND {}",
+            TagFilter::SQL
+        ))?;
+        let (include, exclude) = filter.to_json();
+        let rows = statement.query_map(
+            named_params! { ":poolId": pool_id, ":includeTags": include, ":excludeTags": exclude },
+            Self::from_row,
+        )?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
     }
-}
 
-pub fn create_db(dest: PathBuf) -> Result<Connection> {
-    let now = Instant::now();
-    let mut db = Connection::open_in_memory()?;
-    db = init_db(db)?;
-    match db.backup(DatabaseName::Main, dest, None) {
-        Ok(_) => {
-            debug!(
-                "[DB] Creating and Saving took {} ms.",
-                now.elapsed().as_millis()
-            );
-            Ok(db)
-        }
-        Err(err) => {
-            warn!("Failed to create database file: {}", err);
-            close_db(db)?;
-            Err(err)
-        }
+    /// Cards due before the end of the local day, overdue ones first and never answered ones last.
+    pub fn get_due_today(
+        connection: &Connection,
+        profile_name: &str,
+        category_name: Option<&str>,
+        filter: &TagFilter,
+        limit: u32,
+    ) -> Result<Vec<Card>> {
+        let mut statement = connection.prepare(&format!(
+            "SELECT Card.* FROM Card \
+            LEFT JOIN CardProgress ON CardProgress.cardId = Card.id AND CardProgress.profileName = :profileName \
+            WHERE (dueDate IS NULL OR dueDate < CAST(strftime('%s', 'now', 'localtime', 'start of day', '+1 day', 'utc') AS INTEGER)) \
+            AND (:categoryName IS NULL OR categoryName IN ({})) \
+            AND Card.trashId IS NULL \
+            AND {} \
+            ORDER BY dueDate IS NULL, dueDate \
+            LIMIT :limit",
+            Category::SUBTREE_SQL,
+            TagFilter::SQL
+        ))?;
+        let (include, exclude) = filter.to_json();
+        let rows = statement.query_map(
+            named_params! {
+                ":profileName": profile_name,
+                ":categoryName": category_name,
+                ":includeTags": include,
+                ":excludeTags": exclude,
+                ":limit": limit,
+            },
+            Self::from_row,
+        )?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
     }
-}
 
-pub fn open_db(src: PathBuf) -> Result<Connection> {
-    let now = Instant::now();
-    let db = Connection::open(src)?;
-    debug!("[DB] Opening took {} ms.", now.elapsed().as_millis());
-    Ok(db)
-}
+    pub fn get_with_tag(connection: &Connection, tag_name: &str) -> Result<Vec<Card>> {
+        let mut statement = connection.prepare(
+            "SELECT Card.* FROM Card JOIN CardTag ON CardTag.cardId = Card.id \
+            WHERE CardTag.tagName = :tagName AND Card.trashId IS NULL",
+        )?;
+        let rows = statement.query_map(&[(":tagName", &tag_name)], Self::from_row)?;
 
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Cards whose front or back contains `query`, best matches first. Ignores
+    /// kana vs. katakana, width and long vowels, and also reads `query` as romaji.
+    pub fn search(connection: &Connection, query: &str, limit: u32) -> Result<Vec<Card>> {
+        let terms = normalize::search_terms(query);
+        // Trigrams cannot match anything shorter than three characters, so scan the table instead.
+        let (sql, query) = if terms.iter().all(|term| term.chars().count() >= 3) {
+            (
+                "SELECT Card.* FROM CardSearch JOIN Card ON Card.id = CardSearch.rowid \
+                WHERE CardSearch MATCH :query AND Card.trashId IS NULL ORDER BY rank LIMIT :limit",
+                // Quoted as phrases so characters like `-` or `*` are not read as FTS operators.
+                terms
+                    .iter()
+                    .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+                    .collect::<Vec<_>>()
+                    .join(" OR "),
+            )
+        } else {
+            (
+                "SELECT * FROM Card \
+                WHERE trashId IS NULL AND EXISTS (SELECT 1 FROM json_each(:query) \
+                    WHERE instr(frontFolded, value) > 0 OR instr(backFolded, value) > 0) \
+                ORDER BY length(front) LIMIT :limit",
+                serde_json::to_string(&terms).unwrap_or_default(),
+            )
+        };
+        let mut statement = connection.prepare(sql)?;
+        let rows = statement.query_map(
+            named_params! { ":query": query, ":limit": limit },
+            Self::from_row,
+        )?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Tags the card, creating the tag if it does not exist yet.
+    pub fn add_tag(connection: &Connection, id: i32, tag_name: &str) -> Result<()> {
+        connection.execute(
+            "INSERT OR IGNORE INTO Tag (name) VALUES (?1)",
+            params![tag_name],
+        )?;
+        match connection.execute(
+            "INSERT OR IGNORE INTO CardTag (cardId, tagName) VALUES (?1, ?2)",
+            params![id, tag_name],
+        ) {
+            Ok(_) => {
+                debug!("[DB] Tagged Card {} with '{}'", id, tag_name);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while tagging Card {}: {:?}", id, err);
+                Err(err.into())
+            }
+        }
+    }
+
+    pub fn remove_tag(connection: &Connection, id: i32, tag_name: &str) -> Result<()> {
+        match connection.execute(
+            "DELETE FROM CardTag WHERE cardId = ?1 AND tagName = ?2",
+            params![id, tag_name],
+        ) {
+            Ok(_) => {
+                debug!("[DB] Removed tag '{}' from Card {}", tag_name, id);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while untagging Card {}: {:?}", id, err);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// The profile's progress on the card, the defaults if it never answered it.
+    pub fn get_progress(connection: &Connection, profile_name: &str, id: i32) -> Result<Progress> {
+        let mut statement = connection.prepare(
+            "SELECT score, dueDate, intervalDays, easeFactor, repetitions, stability, difficulty, lastReview \
+            FROM CardProgress WHERE profileName = :profileName AND cardId = :cardId",
+        )?;
+        let progress = statement
+            .query_row(
+                named_params! { ":profileName": profile_name, ":cardId": id },
+                |row| {
+                    Ok(Progress {
+                        score: row.get(0)?,
+                        schedule: Schedule {
+                            due: row.get(1)?,
+                            interval: row.get(2)?,
+                            ease_factor: row.get(3)?,
+                            repetitions: row.get(4)?,
+                            stability: row.get(5)?,
+                            difficulty: row.get(6)?,
+                            last_review: row.get(7)?,
+                        },
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(progress.unwrap_or(Progress {
+            score: 0,
+            schedule: Schedule::default(),
+        }))
+    }
+
+    pub fn set_progress(
+        connection: &Connection,
+        profile_name: &str,
+        id: i32,
+        progress: &Progress,
+    ) -> Result<()> {
+        let schedule = &progress.schedule;
+        match connection.execute(
+            "INSERT OR REPLACE INTO CardProgress \
+            (profileName, cardId, score, dueDate, intervalDays, easeFactor, repetitions, stability, difficulty, lastReview) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                profile_name,
+                id,
+                progress.score,
+                schedule.due,
+                schedule.interval,
+                schedule.ease_factor,
+                schedule.repetitions,
+                schedule.stability,
+                schedule.difficulty,
+                schedule.last_review
+            ],
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!(
+                    "[DB] Failed to update progress of Profile '{}' for ID {}.",
+                    profile_name, id
+                );
+                Err(err.into())
+            }
+        }
+    }
+}
+impl Profile {
+    pub fn create(connection: &Connection, name: String) -> Result<()> {
+        match connection.execute(
+            "INSERT INTO Profile (name, createdAt) VALUES (?1, ?2)",
+            params![name, now_timestamp()],
+        ) {
+            Ok(_) => {
+                info!("[DB] Created new Profile '{}'", name);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while creating new Profile: {:?}", err);
+                Err(err.into())
+            }
+        }
+    }
+    /// Deletes the profile along with its progress and review history.
+    pub fn delete(connection: &Connection, name: String) -> Result<()> {
+        match connection.execute("DELETE FROM Profile WHERE name = ?1", params![name]) {
+            Ok(_) => {
+                debug!("[DB] Deleted Profile '{}'", name);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while deleting Profile: {:?}", err);
+                Err(err.into())
+            }
+        }
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Profile> {
+        Ok(Profile {
+            name: row.get(0)?,
+            created_at: row.get(1)?,
+        })
+    }
+
+    pub fn get_all(connection: &Connection) -> Result<Vec<Profile>> {
+        let mut statement = connection.prepare("SELECT * FROM Profile ORDER BY createdAt, name")?;
+        let rows = statement.query_map([], Self::from_row)?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+    pub fn get_one(connection: &Connection, name: &str) -> Result<Profile> {
+        let mut statement = connection.prepare("SELECT * FROM Profile WHERE name = :name")?;
+        Ok(statement.query_row(&[(":name", &name)], Self::from_row)?)
+    }
+
+    /// Gets the profile, creating it first if it does not exist yet.
+    pub fn get_or_create(connection: &Connection, name: &str) -> Result<Profile> {
+        match Self::get_one(connection, name) {
+            Err(Error::Database(rusqlite::Error::QueryReturnedNoRows)) => {
+                Self::create(connection, name.to_string())?;
+                Self::get_one(connection, name)
+            }
+            result => result,
+        }
+    }
+}
+impl Tag {
+    pub fn create(connection: &Connection, name: String) -> Result<()> {
+        match connection.execute("INSERT INTO Tag (name) VALUES (?1)", params![name]) {
+            Ok(_) => {
+                debug!("[DB] Created new Tag '{}'", name);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while creating new Tag: {:?}", err);
+                Err(err.into())
+            }
+        }
+    }
+
+    pub fn add(connection: &Connection, src: Tag) -> Result<()> {
+        Self::create(connection, src.name)
+    }
+    pub fn delete(connection: &Connection, name: String) -> Result<()> {
+        let result = connection
+            .execute("DELETE FROM CardTag WHERE tagName = ?1", params![name])
+            .and_then(|_| connection.execute("DELETE FROM Tag WHERE name = ?1", params![name]));
+        match result {
+            Ok(_) => {
+                debug!("[DB] Deleted Tag '{}'", name);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while deleting Tag: {:?}", err);
+                Err(err.into())
+            }
+        }
+    }
+
+    pub fn get_all(connection: &Connection) -> Result<Vec<Tag>> {
+        let mut statement = connection.prepare("SELECT * FROM Tag ORDER BY name")?;
+        let rows = statement.query_map([], |row| Ok(Tag { name: row.get(0)? }))?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn get_one(connection: &Connection, name: &str) -> Result<Tag> {
+        let mut statement = connection.prepare("SELECT * FROM Tag WHERE name = :name LIMIT 1")?;
+        let row = statement.query_row(&[(":name", &name)], |row| row.get(0))?;
+
+        Ok(Tag { name: row })
+    }
+
+    pub fn get_for_card(connection: &Connection, card_id: i32) -> Result<Vec<Tag>> {
+        let mut statement = connection
+            .prepare("SELECT tagName FROM CardTag WHERE cardId = :cardId ORDER BY tagName")?;
+        let rows = statement.query_map(&[(":cardId", &card_id)], |row| {
+            Ok(Tag { name: row.get(0)? })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+impl NoteType {
+    /// Inserts the note type and its templates.
+    pub fn add(connection: &Connection, src: &NoteType) -> Result<()> {
+        let result = atomically(connection, || {
+            connection.execute(
+                "INSERT INTO NoteType (name, fields) VALUES (?1, ?2)",
+                params![src.name, fields_to_json(&src.fields)],
+            )?;
+            Self::insert_templates(connection, &src.name, &src.templates)
+        });
+        match result {
+            Ok(_) => {
+                debug!("[DB] Created new NoteType '{}'", src.name);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while creating new NoteType: {:?}", err);
+                Err(err)
+            }
+        }
+    }
+
+    fn insert_templates(
+        connection: &Connection,
+        note_type_name: &str,
+        templates: &[CardTemplate],
+    ) -> Result<()> {
+        let mut statement = connection.prepare(
+            "INSERT INTO CardTemplate (noteTypeName, name, front, back, position) \
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (position, template) in templates.iter().enumerate() {
+            statement.execute(params![
+                note_type_name,
+                template.name,
+                template.front,
+                template.back,
+                position as i32
+            ])?;
+        }
+        Ok(())
+    }
+
+    pub fn get_one(connection: &Connection, name: &str) -> Result<NoteType> {
+        let mut statement =
+            connection.prepare("SELECT fields FROM NoteType WHERE name = :name LIMIT 1")?;
+        let fields: String = statement.query_row(&[(":name", &name)], |row| row.get(0))?;
+        let mut statement = connection.prepare(
+            "SELECT name, front, back FROM CardTemplate \
+            WHERE noteTypeName = :name ORDER BY position",
+        )?;
+        let templates = statement
+            .query_map(&[(":name", &name)], |row| {
+                Ok(CardTemplate {
+                    name: row.get(0)?,
+                    front: row.get(1)?,
+                    back: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(NoteType {
+            name: name.to_string(),
+            fields: serde_json::from_str(&fields).unwrap_or_default(),
+            templates,
+        })
+    }
+
+    pub fn get_all(connection: &Connection) -> Result<Vec<NoteType>> {
+        let mut statement = connection.prepare("SELECT name FROM NoteType ORDER BY name")?;
+        let names = statement
+            .query_map([], |row| row.get::<usize, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        names
+            .iter()
+            .map(|name| Self::get_one(connection, name))
+            .collect()
+    }
+
+    /// Replaces the fields and templates of a note type, then regenerates the cards of all
+    /// its notes. Cards of templates that are kept keep their progress.
+    pub fn update(connection: &Connection, src: &NoteType) -> Result<()> {
+        let result = atomically(connection, || {
+            connection.execute(
+                "UPDATE NoteType SET fields = ?2 WHERE name = ?1",
+                params![src.name, fields_to_json(&src.fields)],
+            )?;
+            connection.execute(
+                "DELETE FROM CardTemplate WHERE noteTypeName = ?1",
+                params![src.name],
+            )?;
+            Self::insert_templates(connection, &src.name, &src.templates)?;
+            let mut statement =
+                connection.prepare("SELECT id FROM Note WHERE noteTypeName = :name")?;
+            let note_ids = statement
+                .query_map(&[(":name", &src.name)], |row| row.get::<usize, i32>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            note_ids
+                .into_iter()
+                .try_for_each(|id| Note::regenerate(connection, id))
+        });
+        match result {
+            Ok(_) => {
+                debug!("[DB] Updated NoteType '{}'", src.name);
+                Ok(())
+            }
+            Err(err) => {
+                error!(
+                    "[DB] Error while updating NoteType '{}': {:?}",
+                    src.name, err
+                );
+                Err(err)
+            }
+        }
+    }
+}
+impl CardTemplate {
+    /// The front and back of the card for `fields`, or `None` if either side comes out
+    /// empty, e.g. because the note leaves a field blank.
+    pub fn render(&self, fields: &BTreeMap<String, String>) -> Option<(String, String)> {
+        // One pass over the template, so `{{...}}` in a field value is left as it is.
+        let fill = |side: &str| {
+            let mut filled = String::with_capacity(side.len());
+            let mut rest = side;
+            while let Some(start) = rest.find("{{") {
+                filled.push_str(&rest[..start]);
+                let after = &rest[start + 2..];
+                let field = after
+                    .find("}}")
+                    .and_then(|end| fields.get(&after[..end]).map(|value| (end, value)));
+                match field {
+                    Some((end, value)) => {
+                        filled.push_str(value);
+                        rest = &after[end + 2..];
+                    }
+                    None => {
+                        filled.push_str("{{");
+                        rest = after;
+                    }
+                }
+            }
+            filled.push_str(rest);
+            filled.trim().to_string()
+        };
+        let (front, back) = (fill(&self.front), fill(&self.back));
+        if front.is_empty() || back.is_empty() {
+            None
+        } else {
+            Some((front, back))
+        }
+    }
+}
+impl Note {
+    /// Inserts the note and generates its cards. Returns the id of the note.
+    pub fn add(connection: &Connection, src: &Note) -> Result<i32> {
+        let result = atomically(connection, || -> Result<i32> {
+            connection.execute(
+                "INSERT INTO Note (id, noteTypeName, poolId, fields) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    src.id,
+                    src.note_type_name,
+                    src.pool_id,
+                    serde_json::to_string(&src.fields).unwrap_or_default()
+                ],
+            )?;
+            let id = connection.last_insert_rowid() as i32;
+            Self::regenerate(connection, id)?;
+            Ok(id)
+        });
+        match result {
+            Ok(id) => {
+                debug!(
+                    "[DB] Created new Note {} of type '{}'",
+                    id, src.note_type_name
+                );
+                Ok(id)
+            }
+            Err(err) => {
+                error!("[DB] Error while creating new Note: {:?}", err);
+                Err(err)
+            }
+        }
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Note> {
+        Ok(Note {
+            id: row.get(0)?,
+            note_type_name: row.get(1)?,
+            pool_id: row.get(2)?,
+            fields: serde_json::from_str(&row.get::<usize, String>(3)?).unwrap_or_default(),
+        })
+    }
+
+    pub fn get_by_id(connection: &Connection, id: i32) -> Result<Note> {
+        let mut statement = connection
+            .prepare("SELECT id, noteTypeName, poolId, fields FROM Note WHERE id = :id LIMIT 1")?;
+        Ok(statement.query_row(&[(":id", &id)], Self::from_row)?)
+    }
+
+    /// The notes whose cards go into the pool.
+    pub fn get_in_pool(connection: &Connection, pool_id: i32) -> Result<Vec<Note>> {
+        let mut statement = connection.prepare(
+            "SELECT id, noteTypeName, poolId, fields FROM Note WHERE poolId = :poolId ORDER BY id",
+        )?;
+        let rows = statement.query_map(&[(":poolId", &pool_id)], Self::from_row)?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Replaces the fields of a note and regenerates its cards.
+    pub fn update_fields(
+        connection: &Connection,
+        id: i32,
+        fields: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let result = atomically(connection, || {
+            connection.execute(
+                "UPDATE Note SET fields = ?2 WHERE id = ?1",
+                params![id, serde_json::to_string(fields).unwrap_or_default()],
+            )?;
+            Self::regenerate(connection, id)
+        });
+        match result {
+            Ok(_) => {
+                debug!("[DB] Updated Note {}", id);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while updating Note {}: {:?}", id, err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Deletes the note along with its cards.
+    pub fn delete(connection: &Connection, id: i32) -> Result<()> {
+        let result = atomically(connection, || {
+            connection.execute(
+                "DELETE FROM Card WHERE id IN (SELECT cardId FROM NoteCard WHERE noteId = ?1)",
+                params![id],
+            )?;
+            connection.execute("DELETE FROM Note WHERE id = ?1", params![id])
+        });
+        match result {
+            Ok(_) => {
+                debug!("[DB] Deleted Note {}", id);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while deleting Note {}: {:?}", id, err);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Brings the cards of a note in line with its fields and the templates of its type:
+    /// updates the text of existing cards (keeping their progress), adds cards for new
+    /// templates and deletes cards whose template is gone or renders empty.
+    pub fn regenerate(connection: &Connection, id: i32) -> Result<()> {
+        let note = Self::get_by_id(connection, id)?;
+        let note_type = NoteType::get_one(connection, &note.note_type_name)?;
+        let pool = Pool::get_by_id(connection, note.pool_id)?;
+        // Fields the note leaves out are blank.
+        let fields: BTreeMap<String, String> = note_type
+            .fields
+            .iter()
+            .map(|name| {
+                (
+                    name.clone(),
+                    note.fields.get(name).cloned().unwrap_or_default(),
+                )
+            })
+            .collect();
+        let mut existing: HashMap<String, i32> = {
+            let mut statement = connection
+                .prepare("SELECT templateName, cardId FROM NoteCard WHERE noteId = :noteId")?;
+            let rows =
+                statement.query_map(&[(":noteId", &id)], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        for template in &note_type.templates {
+            let card_id = existing.remove(&template.name);
+            match (template.render(&fields), card_id) {
+                (Some((front, back)), Some(card_id)) => {
+                    connection.execute(
+                        "UPDATE Card SET front = ?2, back = ?3, frontFolded = ?4, backFolded = ?5 \
+                        WHERE id = ?1",
+                        params![
+                            card_id,
+                            front,
+                            back,
+                            normalize::fold(&front),
+                            normalize::fold(&back)
+                        ],
+                    )?;
+                }
+                (Some((front, back)), None) => {
+                    let card_id = Card::add(
+                        connection,
+                        Card {
+                            id: None,
+                            front,
+                            back,
+                            front_image: PathBuf::new(),
+                            back_image: PathBuf::new(),
+                            pool_id: Some(pool.id),
+                            category_name: pool.category_name.clone(),
+                            front_media: None,
+                            back_media: None,
+                            front_audio: PathBuf::new(),
+                            back_audio: PathBuf::new(),
+                            reading: String::new(),
+                            notes: String::new(),
+                            example: String::new(),
+                        },
+                    )?;
+                    connection.execute(
+                        "INSERT INTO NoteCard (cardId, noteId, templateName) VALUES (?1, ?2, ?3)",
+                        params![card_id, id, template.name],
+                    )?;
+                }
+                (None, Some(card_id)) => Card::delete(connection, card_id)?,
+                (None, None) => {}
+            }
+        }
+        // Left over are the cards of templates that were removed.
+        existing
+            .into_values()
+            .try_for_each(|card_id| Card::delete(connection, card_id))?;
+        debug!("[DB] Regenerated the cards of Note {}", id);
+
+        Ok(())
+    }
+
+    /// The cards generated from the note, by template name.
+    pub fn get_cards(connection: &Connection, id: i32) -> Result<BTreeMap<String, Card>> {
+        let mut statement = connection
+            .prepare("SELECT templateName, cardId FROM NoteCard WHERE noteId = :noteId")?;
+        let rows = statement
+            .query_map(&[(":noteId", &id)], |row| {
+                Ok((row.get::<usize, String>(0)?, row.get::<usize, i32>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        rows.into_iter()
+            .map(|(template_name, card_id)| {
+                Ok((template_name, Card::get_by_id(connection, card_id)?))
+            })
+            .collect()
+    }
+
+    /// The note and template each generated card in the pool came from, by card id.
+    pub fn generated_in_pool(
+        connection: &Connection,
+        pool_id: i32,
+    ) -> Result<HashMap<i32, (i32, String)>> {
+        let mut statement = connection.prepare(
+            "SELECT NoteCard.cardId, NoteCard.noteId, NoteCard.templateName FROM NoteCard \
+            JOIN Card ON Card.id = NoteCard.cardId WHERE Card.poolId = :poolId",
+        )?;
+        let rows = statement.query_map(&[(":poolId", &pool_id)], |row| {
+            Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The cards generated from `template_name` for the other notes of the same type as
+    /// `note_id`, in any pool. Cards in the trash are left out.
+    pub fn template_siblings(
+        connection: &Connection,
+        note_id: i32,
+        template_name: &str,
+    ) -> Result<Vec<Card>> {
+        let mut statement = connection.prepare(
+            "SELECT Card.* FROM NoteCard JOIN Card ON Card.id = NoteCard.cardId \
+            JOIN Note ON Note.id = NoteCard.noteId \
+            WHERE Note.noteTypeName = (SELECT noteTypeName FROM Note WHERE id = :noteId) \
+            AND NoteCard.noteId != :noteId AND NoteCard.templateName = :templateName \
+            AND Card.trashId IS NULL",
+        )?;
+        let rows = statement.query_map(
+            named_params! { ":noteId": note_id, ":templateName": template_name },
+            Card::from_row,
+        )?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+fn fields_to_json(fields: &[String]) -> String {
+    serde_json::to_string(fields).unwrap_or_default()
+}
+impl Media {
+    /// The key `data` is stored under.
+    pub fn hash_of(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    /// Stores `data` unless the same bytes already are, and returns its hash.
+    pub fn add(connection: &Connection, data: &[u8]) -> Result<String> {
+        let hash = Self::hash_of(data);
+        match connection.execute(
+            "INSERT OR IGNORE INTO Media (hash, data, createdAt) VALUES (?1, ?2, ?3)",
+            params![hash, data, now_timestamp()],
+        ) {
+            Ok(0) => {
+                debug!("[DB] Media {} already stored", hash);
+                Ok(hash)
+            }
+            Ok(_) => {
+                debug!("[DB] Stored Media {} ({} bytes)", hash, data.len());
+                Ok(hash)
+            }
+            Err(err) => {
+                error!("[DB] Error while storing Media: {:?}", err);
+                Err(err.into())
+            }
+        }
+    }
+
+    pub fn get(connection: &Connection, hash: &str) -> Result<Media> {
+        Ok(connection.query_row(
+            "SELECT hash, data FROM Media WHERE hash = :hash",
+            &[(":hash", &hash)],
+            |row| {
+                Ok(Media {
+                    hash: row.get(0)?,
+                    data: row.get(1)?,
+                })
+            },
+        )?)
+    }
+
+    /// Deletes media no card uses anymore. Returns how many were deleted.
+    pub fn delete_unused(connection: &Connection) -> Result<usize> {
+        let deleted = connection.execute(
+            "DELETE FROM Media WHERE hash NOT IN \
+            (SELECT frontMedia FROM Card WHERE frontMedia IS NOT NULL \
+            UNION SELECT backMedia FROM Card WHERE backMedia IS NOT NULL)",
+            (),
+        )?;
+        debug!("[DB] Deleted {} unused Media", deleted);
+        Ok(deleted)
+    }
+}
+
+impl TrashedItem {
+    fn kind(&self) -> &'static str {
+        match self {
+            TrashedItem::Card(_) => "card",
+            TrashedItem::Pool(_) => "pool",
+            TrashedItem::Category(_) => "category",
+        }
+    }
+
+    fn key(&self) -> String {
+        match self {
+            TrashedItem::Card(id) | TrashedItem::Pool(id) => id.to_string(),
+            TrashedItem::Category(name) => name.clone(),
+        }
+    }
+}
+impl std::fmt::Display for TrashedItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrashedItem::Card(id) => write!(f, "Card {}", id),
+            TrashedItem::Pool(id) => write!(f, "Pool {}", id),
+            TrashedItem::Category(name) => write!(f, "Category '{}'", name),
+        }
+    }
+}
+
+impl Trash {
+    /// Puts `item` and everything in it that is not in the trash yet into a new entry.
+    /// Fails with [`rusqlite::Error::QueryReturnedNoRows`] if the item does not exist or is
+    /// already in the trash.
+    fn create(connection: &Connection, item: TrashedItem) -> Result<i64> {
+        let result = atomically(connection, || {
+            connection.execute(
+                "INSERT INTO Trash (kind, itemKey, deletedAt) VALUES (?1, ?2, ?3)",
+                params![item.kind(), item.key(), now_timestamp()],
+            )?;
+            let id = connection.last_insert_rowid();
+            let trashed = match &item {
+                TrashedItem::Card(card_id) => connection.execute(
+                    "UPDATE Card SET trashId = ?1 WHERE id = ?2 AND trashId IS NULL",
+                    params![id, card_id],
+                )?,
+                TrashedItem::Pool(pool_id) => {
+                    let trashed = connection.execute(
+                        "UPDATE Pool SET trashId = ?1 WHERE id = ?2 AND trashId IS NULL",
+                        params![id, pool_id],
+                    )?;
+                    connection.execute(
+                        "UPDATE Card SET trashId = ?1 WHERE poolId = ?2 AND trashId IS NULL",
+                        params![id, pool_id],
+                    )?;
+                    trashed
+                }
+                TrashedItem::Category(name) => {
+                    let params = named_params! { ":trashId": id, ":categoryName": name };
+                    let trashed = connection.execute(
+                        &format!(
+                            "UPDATE Category SET trashId = :trashId \
+                            WHERE name IN ({}) AND trashId IS NULL",
+                            Category::SUBTREE_SQL
+                        ),
+                        params,
+                    )?;
+                    connection.execute(
+                        &format!(
+                            "UPDATE Pool SET trashId = :trashId \
+                            WHERE categoryName IN ({}) AND trashId IS NULL",
+                            Category::SUBTREE_SQL
+                        ),
+                        params,
+                    )?;
+                    connection.execute(
+                        &format!(
+                            "UPDATE Card SET trashId = :trashId \
+                            WHERE (categoryName IN ({}) \
+                                OR poolId IN (SELECT id FROM Pool WHERE trashId = :trashId)) \
+                            AND trashId IS NULL",
+                            Category::SUBTREE_SQL
+                        ),
+                        params,
+                    )?;
+                    trashed
+                }
+            };
+            if trashed == 0 {
+                return Err(rusqlite::Error::QueryReturnedNoRows.into());
+            }
+            Ok(id)
+        });
+        match &result {
+            Ok(id) => debug!("[DB] Moved {} to the trash as entry {}", item, id),
+            Err(err) => error!("[DB] Error while moving {} to the trash: {:?}", item, err),
+        }
+        result
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Trash> {
+        let kind: String = row.get(1)?;
+        let key: String = row.get(2)?;
+        let parse_id = |key: &str| {
+            key.parse().map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(err))
+            })
+        };
+        let item = match kind.as_str() {
+            "card" => TrashedItem::Card(parse_id(&key)?),
+            "pool" => TrashedItem::Pool(parse_id(&key)?),
+            "category" => TrashedItem::Category(key),
+            _ => return Err(rusqlite::Error::InvalidColumnType(1, kind, Type::Text)),
+        };
+        Ok(Trash {
+            id: row.get(0)?,
+            item,
+            deleted_at: row.get(3)?,
+            card_count: row.get(4)?,
+        })
+    }
+
+    /// Everything in the trash, most recently trashed first.
+    pub fn get_all(connection: &Connection) -> Result<Vec<Trash>> {
+        let mut statement = connection.prepare(
+            "SELECT id, kind, itemKey, deletedAt, \
+            (SELECT COUNT(*) FROM Card WHERE Card.trashId = Trash.id) \
+            FROM Trash ORDER BY deletedAt DESC, id DESC",
+        )?;
+        let rows = statement.query_map([], Self::from_row)?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Takes everything in the entry out of the trash. Items trashed on their own before
+    /// stay in their own entries.
+    pub fn restore(connection: &Connection, id: i64) -> Result<()> {
+        match connection.execute("DELETE FROM Trash WHERE id = ?1", params![id]) {
+            Ok(0) => Err(rusqlite::Error::QueryReturnedNoRows.into()),
+            Ok(_) => {
+                debug!("[DB] Restored trash entry {}", id);
+                Ok(())
+            }
+            Err(err) => {
+                error!("[DB] Error while restoring trash entry {}: {:?}", id, err);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Deletes everything in the trash for good. Returns how many cards were deleted.
+    pub fn empty(connection: &Connection) -> Result<usize> {
+        let result = atomically(connection, || {
+            let cards = connection.execute("DELETE FROM Card WHERE trashId IS NOT NULL", ())?;
+            connection.execute("DELETE FROM Pool WHERE trashId IS NOT NULL", ())?;
+            connection.execute("DELETE FROM Category WHERE trashId IS NOT NULL", ())?;
+            connection.execute("DELETE FROM Trash", ())?;
+            Ok(cards)
+        });
+        match &result {
+            Ok(cards) => debug!("[DB] Emptied the trash, deleting {} Cards", cards),
+            Err(err) => error!("[DB] Error while emptying the trash: {:?}", err),
+        }
+        result
+    }
+}
+
+impl TagFilter {
+    /// Condition on `Card.id`, bound to `:includeTags` and `:excludeTags` from [`TagFilter::to_json`].
+    const SQL: &'static str = "(json_array_length(:includeTags) = 0 \
+        OR id IN (SELECT cardId FROM CardTag WHERE tagName IN (SELECT value FROM json_each(:includeTags)))) \
+        AND id NOT IN (SELECT cardId FROM CardTag WHERE tagName IN (SELECT value FROM json_each(:excludeTags)))";
+
+    fn to_json(&self) -> (String, String) {
+        (
+            serde_json::Value::from(self.include.clone()).to_string(),
+            serde_json::Value::from(self.exclude.clone()).to_string(),
+        )
+    }
+}
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            due: None,
+            interval: 0,
+            ease_factor: 2.5,
+            repetitions: 0,
+            stability: None,
+            difficulty: None,
+            last_review: None,
+        }
+    }
+}
+impl Answer {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Answer::Correct => "correct",
+            Answer::Incorrect => "incorrect",
+            Answer::DontKnow => "dont_know",
+        }
+    }
+}
+impl ToSql for Answer {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+impl FromSql for Answer {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "correct" => Ok(Answer::Correct),
+            "incorrect" => Ok(Answer::Incorrect),
+            "dont_know" => Ok(Answer::DontKnow),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+impl Review {
+    pub fn add(connection: &Connection, src: Review) -> Result<()> {
+        match connection.execute(
+            "INSERT INTO \
+            Review (cardId, timestamp, chosenOption, answer, responseTimeMs, scoreBefore, scoreAfter, profileName, sessionId) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                src.card_id,
+                src.timestamp,
+                src.chosen_option,
+                src.answer,
+                src.response_time.as_millis() as i64,
+                src.score_before,
+                src.score_after,
+                src.profile_name,
+                src.session_id
+            ],
+        ) {
+            Ok(_) => {
+                debug!(
+                    "[DB] Recorded {:?} answer for Card {}",
+                    src.answer, src.card_id
+                );
+                Ok(())
+            }
+            Err(err) => {
+                error!(
+                    "[DB] Error while recording review for Card {}: {:?}",
+                    src.card_id, err
+                );
+                Err(err.into())
+            }
+        }
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Review> {
+        Ok(Review {
+            id: row.get(0)?,
+            card_id: row.get(1)?,
+            timestamp: row.get(2)?,
+            chosen_option: row.get(3)?,
+            answer: row.get(4)?,
+            response_time: Duration::from_millis(row.get::<usize, i64>(5)?.max(0) as u64),
+            score_before: row.get(6)?,
+            score_after: row.get(7)?,
+            profile_name: row.get(8)?,
+            session_id: row.get(9)?,
+        })
+    }
+
+    /// All reviews of a card, oldest first.
+    pub fn get_for_card(connection: &Connection, card_id: i32) -> Result<Vec<Review>> {
+        let mut statement = connection
+            .prepare("SELECT * FROM Review WHERE cardId = :cardId ORDER BY timestamp, id")?;
+        let rows = statement.query_map(&[(":cardId", &card_id)], Self::from_row)?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn get_latest_for_card(connection: &Connection, card_id: i32) -> Result<Option<Review>> {
+        let mut statement = connection.prepare(
+            "SELECT * FROM Review WHERE cardId = :cardId ORDER BY timestamp DESC, id DESC LIMIT 1",
+        )?;
+        Ok(statement
+            .query_row(&[(":cardId", &card_id)], Self::from_row)
+            .optional()?)
+    }
+}
+
+impl SessionMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SessionMode::RandomPools => "random_pools",
+            SessionMode::DueToday => "due_today",
+        }
+    }
+}
+impl ToSql for SessionMode {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+impl FromSql for SessionMode {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "random_pools" => Ok(SessionMode::RandomPools),
+            "due_today" => Ok(SessionMode::DueToday),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+impl Session {
+    /// Records the start of a quiz and returns the new session's id.
+    pub fn start(connection: &Connection, src: Session) -> Result<i64> {
+        match connection.execute(
+            "INSERT INTO \
+            Session (profileName, startedAt, categoryName, mode, questionCount, choicesCount) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                src.profile_name,
+                src.started_at,
+                src.category_name,
+                src.mode,
+                src.question_count,
+                src.choices_count
+            ],
+        ) {
+            Ok(_) => {
+                let id = connection.last_insert_rowid();
+                debug!(
+                    "[DB] Started Session {} for Profile '{}'",
+                    id, src.profile_name
+                );
+                Ok(id)
+            }
+            Err(err) => {
+                error!("[DB] Error while starting Session: {:?}", err);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Tallies the session's results from its reviews and marks it as ended, unless it
+    /// already was.
+    pub fn finish(connection: &Connection, id: i64) -> Result<Session> {
+        match connection.execute(
+            "UPDATE Session SET \
+            endedAt = COALESCE(endedAt, :now), \
+            correct = (SELECT COUNT(*) FROM Review WHERE sessionId = :id AND answer = 'correct'), \
+            incorrect = (SELECT COUNT(*) FROM Review WHERE sessionId = :id AND answer = 'incorrect'), \
+            dontKnow = (SELECT COUNT(*) FROM Review WHERE sessionId = :id AND answer = 'dont_know'), \
+            scoreDelta = (SELECT COALESCE(SUM(scoreAfter - scoreBefore), 0) FROM Review WHERE sessionId = :id) \
+            WHERE id = :id",
+            named_params! { ":id": id, ":now": now_timestamp() },
+        ) {
+            Ok(_) => {
+                debug!("[DB] Finished Session {}", id);
+                Self::get_by_id(connection, id)
+            }
+            Err(err) => {
+                error!("[DB] Error while finishing Session {}: {:?}", id, err);
+                Err(err.into())
+            }
+        }
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Session> {
+        Ok(Session {
+            id: row.get(0)?,
+            profile_name: row.get(1)?,
+            started_at: row.get(2)?,
+            ended_at: row.get(3)?,
+            category_name: row.get(4)?,
+            mode: row.get(5)?,
+            question_count: row.get(6)?,
+            choices_count: row.get(7)?,
+            correct: row.get(8)?,
+            incorrect: row.get(9)?,
+            dont_know: row.get(10)?,
+            score_delta: row.get(11)?,
+        })
+    }
+
+    pub fn get_by_id(connection: &Connection, id: i64) -> Result<Session> {
+        let mut statement = connection.prepare("SELECT * FROM Session WHERE id = :id")?;
+        Ok(statement.query_row(&[(":id", &id)], Self::from_row)?)
+    }
+    /// The profile's sessions, latest first.
+    pub fn get_for_profile(connection: &Connection, profile_name: &str) -> Result<Vec<Session>> {
+        let mut statement = connection.prepare(
+            "SELECT * FROM Session WHERE profileName = :profileName ORDER BY startedAt DESC, id DESC",
+        )?;
+        let rows = statement.query_map(&[(":profileName", &profile_name)], Self::from_row)?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Cards that were not answered correctly in the session, in the order they were asked.
+    pub fn get_missed_cards(connection: &Connection, id: i64) -> Result<Vec<Card>> {
+        let mut statement = connection.prepare(
+            "SELECT Card.* FROM Card JOIN Review ON Review.cardId = Card.id \
+            WHERE Review.sessionId = :id AND Review.answer != 'correct' \
+            GROUP BY Card.id ORDER BY MIN(Review.id)",
+        )?;
+        let rows = statement.query_map(&[(":id", &id)], Card::from_row)?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+/// Current time as seconds since the Unix epoch, the format every timestamp in the database uses.
+pub fn now_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64)
+}
+
+/// Runs `f` all-or-nothing. Uses a savepoint, so this also works inside an outer transaction.
+pub fn atomically<T, E: From<rusqlite::Error>>(
+    connection: &Connection,
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    connection.execute_batch("SAVEPOINT atomically")?;
+    match f() {
+        Ok(value) => {
+            connection.execute_batch("RELEASE atomically")?;
+            Ok(value)
+        }
+        Err(err) => {
+            connection.execute_batch("ROLLBACK TO atomically; RELEASE atomically")?;
+            Err(err)
+        }
+    }
+}
+
+/// The directory relative image and sound paths are resolved against: `media_dir` if given,
+/// otherwise the one the database at `db_path` is in. Made absolute, so that resolved
+/// paths do not depend on where the binary was started from.
+pub fn media_dir(db_path: &Path, media_dir: Option<&Path>) -> PathBuf {
+    let dir = match media_dir {
+        Some(dir) => dir,
+        None => db_path.parent().unwrap_or(Path::new("")),
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf())
+}
+
+/// Where the image or sound at `path` is, given the [`media_dir`]. Absolute paths are
+/// kept as they are.
+pub fn resolve_path(media_dir: &Path, path: &Path) -> PathBuf {
+    media_dir.join(path)
+}
+
+/// Opens the database at `src`, creating it if it does not exist, and migrates it to the
+/// current schema.
+pub fn create_or_open(src: PathBuf) -> Result<Connection> {
+    if src.exists() {
+        info!("[DB] Opening existing Database");
+        open_db(src)
+    } else {
+        info!("[DB] Creating new Database");
+        create_db(src)
+    }
+}
+
+pub fn create_db(dest: PathBuf) -> Result<Connection> {
+    let now = Instant::now();
+    let db = Connection::open(&dest)?;
+    configure(&db)?;
+    info!("[DB INIT] Creating tables");
+    if let Err(err) = migrations::migrate(&db, None) {
+        warn!("Failed to create database file: {}", err);
+        close_db(db)?;
+        // Half a schema would be taken for a database to migrate when opened next time.
+        if let Err(err) = std::fs::remove_file(&dest) {
+            warn!("Cannot remove unfinished database file {:?}: {}", dest, err);
+        }
+        return Err(err.into());
+    }
+    info!("[DB INIT] Database Creation Successful!");
+    debug!(
+        "[DB] Creating and Saving took {} ms.",
+        now.elapsed().as_millis()
+    );
+    Ok(db)
+}
+
+pub fn open_db(src: PathBuf) -> Result<Connection> {
+    let now = Instant::now();
+    let db = Connection::open(&src)?;
+    configure(&db)?;
+    if let Err(err) = migrations::migrate(&db, Some(&src)) {
+        close_db(db)?;
+        return Err(err.into());
+    }
+    debug!("[DB] Opening took {} ms.", now.elapsed().as_millis());
+    Ok(db)
+}
+
+/// A fresh database that only lives as long as the connection.
+pub fn open_in_memory() -> Result<Connection> {
+    let db = Connection::open_in_memory()?;
+    configure(&db)?;
+    migrations::migrate(&db, None)?;
+    Ok(db)
+}
+
+/// Per-connection settings. SQLite ignores foreign keys unless asked not to,
+/// and every `ON UPDATE CASCADE`/`ON DELETE` in the schema depends on them.
+pub(crate) fn configure(connection: &Connection) -> Result<()> {
+    Ok(connection.pragma_update(None, "foreign_keys", true)?)
+}
+
+/// Closes the connection, retrying a few times if SQLite is still busy.
 pub fn close_db(connection: Connection) -> Result<()> {
     info!("[DB] Closing Database");
     let mut fails = 0;
@@ -331,7 +2082,7 @@ pub fn close_db(connection: Connection) -> Result<()> {
             fails += 1;
             if fails > 3 {
                 error!("[DB] Cannot close connection. Giving up.");
-                res = Err(e);
+                res = Err(e.into());
                 break;
             }
             _ = opt_conn.insert(con);
@@ -342,52 +2093,562 @@ pub fn close_db(connection: Connection) -> Result<()> {
     res
 }
 
-fn init_db(conn: Connection) -> Result<Connection> {
-    info!("[DB INIT] Creating tables");
-    conn.execute(
-        "CREATE TABLE Category (
-              name TEXT NOT NULL,
-              PRIMARY KEY (name)
-            )",
-        (),
-    )?;
-    info!("[DB INIT] Created table Category");
-    conn.execute(
-        "CREATE TABLE Pool (
-              id INTEGER NOT NULL PRIMARY KEY,
-              categoryName TEXT,
-              FOREIGN KEY (categoryName) REFERENCES Category(name) ON DELETE SET NULL ON UPDATE CASCADE
-            )", (),
-    )?;
-    info!("[DB INIT] Created table Pool");
-    conn.execute(
-        "CREATE TABLE Card (
-              id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-              front TEXT NOT NULL,
-              back TEXT NOT NULL,
-              frontImage TEXT NOT NULL,
-              backImage TEXT NOT NULL,
-              score INTEGER NOT NULL,
-              poolId INTEGER,
-              categoryName TEXT,
-              FOREIGN KEY (poolId) REFERENCES Pool(id) ON DELETE SET NULL ON UPDATE CASCADE,
-              FOREIGN KEY (categoryName) REFERENCES Category(name) ON DELETE SET NULL ON UPDATE CASCADE
-            )", (),
-    )?;
-    info!("[DB INIT] Created table Card");
-    conn.execute("CREATE INDEX Card_poolId_idx ON Card(poolId)", ())?;
-    info!("[DB INIT] Created index Card_poolId_idx");
-    conn.execute(
-        "CREATE INDEX Card_categoryName_idx ON Card(categoryName)",
-        (),
-    )?;
-    info!("[DB INIT] Created index Card_categoryName_idx");
-    conn.execute(
-        "CREATE INDEX Pool_categoryName_idx ON Pool(categoryName)",
-        (),
-    )?;
-    info!("[DB INIT] Created index Pool_categoryName_idx");
-    info!("[DB INIT] Database Creation Successful!");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{card, setup, PROFILE};
+
+    #[test]
+    fn category_rename_cascades() {
+        let conn = setup();
+        Category::create(&conn, "Zoo".to_string(), None, None).unwrap();
+        Category::set_parent(&conn, "Animals", Some("Zoo")).unwrap();
+        Category::rename(&conn, "Animals", "Mammals").unwrap();
+
+        assert_eq!(
+            Pool::get_by_id(&conn, 1).unwrap().category_name.as_deref(),
+            Some("Mammals")
+        );
+        assert!(Card::get_in_pool(&conn, 1)
+            .unwrap()
+            .iter()
+            .all(|c| c.category_name.as_deref() == Some("Mammals")));
+
+        Category::rename(&conn, "Zoo", "Safari").unwrap();
+        assert_eq!(
+            Category::get_one(&conn, "Mammals")
+                .unwrap()
+                .parent_name
+                .as_deref(),
+            Some("Safari")
+        );
+    }
+
+    #[test]
+    fn pool_rename_cascades() {
+        let conn = setup();
+        Pool::rename(&conn, 1, 10).unwrap();
+
+        assert!(Card::get_in_pool(&conn, 1).unwrap().is_empty());
+        assert_eq!(Card::get_in_pool(&conn, 10).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn pool_reassign_category_moves_cards() {
+        let conn = setup();
+        Pool::reassign_category(&conn, 1, Some("Lonely".to_string())).unwrap();
+
+        assert_eq!(
+            Pool::get_by_id(&conn, 1).unwrap().category_name.as_deref(),
+            Some("Lonely")
+        );
+        assert_eq!(
+            Card::get_in_category(&conn, "Lonely".to_string())
+                .unwrap()
+                .len(),
+            5
+        );
+    }
+
+    #[test]
+    fn card_ids_are_assigned_by_sqlite_and_not_reused() {
+        let conn = setup();
+        let created = Card::create(&conn, card("蛇", "snake", 1, "Animals")).unwrap();
+        let id = created.id.unwrap();
+        assert_eq!(Card::get_by_id(&conn, id).unwrap().back, "snake");
+
+        Card::delete(&conn, id).unwrap();
+        let ids = Card::add_all(
+            &conn,
+            &[
+                card("蛙", "frog", 1, "Animals"),
+                card("馬", "horse", 2, "Lonely"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(ids, vec![id + 1, id + 2]);
+
+        // The second card reuses an id, so neither is inserted.
+        let mut taken = card("牛", "cow", 1, "Animals");
+        taken.id = Some(id + 1);
+        assert!(Card::add_all(&conn, &[card("羊", "sheep", 1, "Animals"), taken]).is_err());
+        assert_eq!(Card::get_all(&conn).unwrap().len(), 7);
+    }
+
+    #[test]
+    fn pool_ids_are_assigned_by_sqlite_unless_given() {
+        let conn = setup();
+        let created = Pool::create(&conn, None, Some("Lonely".to_string())).unwrap();
+        assert_eq!(created.id, 3);
+        assert_eq!(
+            Pool::get_by_id(&conn, 3).unwrap().category_name.as_deref(),
+            Some("Lonely")
+        );
+
+        let given = Pool {
+            id: 10,
+            category_name: None,
+        };
+        assert_eq!(Pool::add(&conn, given).unwrap(), 10);
+        assert!(Pool::create(&conn, Some(10), None).is_err());
+    }
+
+    #[test]
+    fn trash_hides_items_until_restored_or_emptied() {
+        let conn = setup();
+        Category::create(&conn, "Zoo".to_string(), None, None).unwrap();
+        Category::set_parent(&conn, "Animals", Some("Zoo")).unwrap();
+        let dog = Card::get_all(&conn).unwrap()[0].id.unwrap();
+        let card_entry = Card::trash(&conn, dog).unwrap();
+        assert_eq!(Card::get_in_pool(&conn, 1).unwrap().len(), 3);
+        assert!(Card::trash(&conn, dog).is_err());
+
+        let category_entry = Category::trash(&conn, "Zoo").unwrap();
+        assert_eq!(
+            Category::get_all(&conn)
+                .unwrap()
+                .iter()
+                .map(|category| category.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Lonely"]
+        );
+        assert!(Pool::get_all_in_category_tree(&conn, "Zoo")
+            .unwrap()
+            .is_empty());
+        assert_eq!(Card::get_all(&conn).unwrap().len(), 1);
+        let entries: Vec<_> = Trash::get_all(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.id, entry.item, entry.card_count))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (category_entry, TrashedItem::Category("Zoo".to_string()), 3),
+                (card_entry, TrashedItem::Card(dog), 1),
+            ]
+        );
+
+        // The card was trashed on its own, so it stays in the trash with the category back.
+        Trash::restore(&conn, category_entry).unwrap();
+        assert_eq!(Category::get_all(&conn).unwrap().len(), 3);
+        assert_eq!(Card::get_in_pool(&conn, 1).unwrap().len(), 3);
 
-    Ok(conn)
+        assert_eq!(Trash::empty(&conn).unwrap(), 1);
+        assert!(Card::get_by_id(&conn, dog).is_err());
+        assert!(Trash::get_all(&conn).unwrap().is_empty());
+        assert_eq!(
+            Pool::get_by_id(&conn, 1).unwrap().category_name.as_deref(),
+            Some("Animals")
+        );
+    }
+
+    #[test]
+    fn category_delete_leaves_pools_without_one() {
+        let conn = setup();
+        Category::delete(&conn, "Lonely".to_string()).unwrap();
+        assert!(Category::get_one(&conn, "Lonely").is_err());
+        assert_eq!(Pool::get_by_id(&conn, 2).unwrap().category_name, None);
+        assert_eq!(Card::get_in_pool(&conn, 2).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn pools_come_back_before_the_category_trashed_after_them() {
+        let conn = setup();
+        let pool_entry = Pool::trash(&conn, 1).unwrap();
+        let category_entry = Category::trash(&conn, "Animals").unwrap();
+        // Everything in Animals was already in the pool's entry.
+        assert_eq!(
+            Trash::get_all(&conn)
+                .unwrap()
+                .iter()
+                .map(|entry| (entry.id, entry.card_count))
+                .collect::<Vec<_>>(),
+            vec![(category_entry, 0), (pool_entry, 4)]
+        );
+
+        Trash::restore(&conn, pool_entry).unwrap();
+        assert!(!Pool::in_trash(&conn, 1).unwrap());
+        assert_eq!(Card::get_in_pool(&conn, 1).unwrap().len(), 4);
+        assert!(Category::in_trash(&conn, "Animals").unwrap());
+        assert_eq!(
+            Pool::get_by_id(&conn, 1).unwrap().category_name.as_deref(),
+            Some("Animals")
+        );
+
+        Trash::restore(&conn, category_entry).unwrap();
+        assert!(!Category::in_trash(&conn, "Animals").unwrap());
+        assert_eq!(
+            Pool::get_all_in_category_tree(&conn, "Animals")
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(Trash::get_all(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn card_update_keeps_id_and_progress() {
+        let conn = setup();
+        let id = Card::get_all(&conn).unwrap()[0].id.unwrap();
+        let progress = Progress {
+            score: 3,
+            schedule: Schedule::default(),
+        };
+        Card::set_progress(&conn, PROFILE, id, &progress).unwrap();
+        Card::update(&conn, id, card("犬", "doggo", 2, "Lonely")).unwrap();
+
+        let updated = Card::get_by_id(&conn, id).unwrap();
+        assert_eq!(updated.back, "doggo");
+        assert_eq!(updated.pool_id, Some(1));
+        assert_eq!(Card::get_progress(&conn, PROFILE, id).unwrap(), progress);
+    }
+
+    #[test]
+    fn profiles_have_separate_progress() {
+        let conn = setup();
+        let id = Card::get_all(&conn).unwrap()[0].id.unwrap();
+        Profile::create(&conn, "partner".to_string()).unwrap();
+        let progress = Progress {
+            score: 5,
+            schedule: Schedule {
+                due: Some(now_timestamp() + 86400 * 7),
+                ..Schedule::default()
+            },
+        };
+        Card::set_progress(&conn, "partner", id, &progress).unwrap();
+
+        assert_eq!(Card::get_progress(&conn, "partner", id).unwrap(), progress);
+        assert_eq!(Card::get_progress(&conn, PROFILE, id).unwrap().score, 0);
+        let filter = TagFilter::default();
+        assert_eq!(
+            Card::get_due_today(&conn, "partner", None, &filter, 10)
+                .unwrap()
+                .len(),
+            4
+        );
+        assert_eq!(
+            Card::get_due_today(&conn, PROFILE, None, &filter, 10)
+                .unwrap()
+                .len(),
+            5
+        );
+
+        Profile::delete(&conn, "partner".to_string()).unwrap();
+        assert_eq!(Card::get_progress(&conn, "partner", id).unwrap().score, 0);
+    }
+
+    #[test]
+    fn card_move_to_pool_follows_category() {
+        let conn = setup();
+        let id = Card::get_all(&conn).unwrap()[0].id.unwrap();
+        Card::move_to_pool(&conn, id, 2).unwrap();
+
+        let moved = Card::get_by_id(&conn, id).unwrap();
+        assert_eq!(moved.pool_id, Some(2));
+        assert_eq!(moved.category_name.as_deref(), Some("Lonely"));
+    }
+
+    #[test]
+    fn card_move_to_missing_pool_fails() {
+        let conn = setup();
+        let id = Card::get_all(&conn).unwrap()[0].id.unwrap();
+
+        assert!(Card::move_to_pool(&conn, id, 99).is_err());
+        assert_eq!(Card::get_by_id(&conn, id).unwrap().pool_id, Some(1));
+    }
+
+    #[test]
+    fn tag_filters_include_any_and_exclude_all() {
+        let conn = setup();
+        let ids: Vec<i32> = Card::get_in_pool(&conn, 1)
+            .unwrap()
+            .iter()
+            .map(|card| card.id.unwrap())
+            .collect();
+        let (dog, cat, bird, fish) = (ids[0], ids[1], ids[2], ids[3]);
+        Card::add_tag(&conn, dog, "pet").unwrap();
+        Card::add_tag(&conn, cat, "pet").unwrap();
+        Card::add_tag(&conn, cat, "noisy").unwrap();
+        Card::add_tag(&conn, bird, "wild").unwrap();
+        let filtered = |include: &[&str], exclude: &[&str]| {
+            let filter = TagFilter {
+                include: include.iter().map(|tag| tag.to_string()).collect(),
+                exclude: exclude.iter().map(|tag| tag.to_string()).collect(),
+            };
+            let mut ids: Vec<i32> = Card::get_in_pool_filtered(&conn, 1, &filter)
+                .unwrap()
+                .iter()
+                .map(|card| card.id.unwrap())
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(filtered(&[], &[]), vec![dog, cat, bird, fish]);
+        assert_eq!(filtered(&["pet"], &[]), vec![dog, cat]);
+        assert_eq!(filtered(&["pet", "wild"], &[]), vec![dog, cat, bird]);
+        assert_eq!(filtered(&[], &["noisy"]), vec![dog, bird, fish]);
+        assert_eq!(filtered(&["pet"], &["noisy", "wild"]), vec![dog]);
+        assert!(filtered(&["missing"], &[]).is_empty());
+
+        let due = Card::get_due_today(
+            &conn,
+            PROFILE,
+            Some("Animals"),
+            &TagFilter {
+                include: vec!["pet".to_string()],
+                exclude: vec!["noisy".to_string()],
+            },
+            10,
+        )
+        .unwrap();
+        assert_eq!(
+            due.iter().map(|card| card.id).collect::<Vec<_>>(),
+            vec![Some(dog)]
+        );
+    }
+
+    #[test]
+    fn card_delete_cascades_to_tags() {
+        let conn = setup();
+        let id = Card::get_all(&conn).unwrap()[0].id.unwrap();
+        Card::add_tag(&conn, id, "animal").unwrap();
+        Card::delete(&conn, id).unwrap();
+
+        assert!(Card::get_with_tag(&conn, "animal").unwrap().is_empty());
+        assert!(Tag::get_for_card(&conn, id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn search_follows_card_edits() {
+        let conn = setup();
+        let id = Card::get_all(&conn).unwrap()[0].id.unwrap();
+        let mut edited = Card::get_by_id(&conn, id).unwrap();
+        edited.back = "hound dog".to_string();
+        Card::update(&conn, id, edited).unwrap();
+
+        let found = Card::search(&conn, "hound", 10).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, Some(id));
+        assert_eq!(Card::search(&conn, "猫", 10).unwrap().len(), 1);
+
+        Card::delete(&conn, id).unwrap();
+        assert!(Card::search(&conn, "hound", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn other_clients_can_write_cards() {
+        let path = std::env::temp_dir().join(format!("fukushuu-plain-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let conn = create_db(path.clone()).unwrap();
+        Category::create(&conn, "Food".to_string(), None, None).unwrap();
+        Pool::create(&conn, Some(1), Some("Food".to_string())).unwrap();
+        close_db(conn).unwrap();
+
+        // Like the sqlite3 shell: nothing the library registers on its connections.
+        let plain = Connection::open(&path).unwrap();
+        plain
+            .execute_batch(
+                "INSERT INTO Card (front, back, frontImage, backImage, poolId, categoryName) \
+                VALUES ('ラーメン', 'ramen', '', '', 1, 'Food'), ('すし', 'sushi', '', '', 1, 'Food');
+                UPDATE Card SET front = 'スシ' WHERE back = 'sushi';",
+            )
+            .unwrap();
+        plain.close().unwrap();
+
+        let conn = open_db(path.clone()).unwrap();
+        let fronts: Vec<String> = Card::get_all(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|card| card.front)
+            .collect();
+        assert_eq!(fronts, ["ラーメン", "スシ"]);
+        close_db(conn).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn session_finish_tallies_reviews() {
+        let conn = setup();
+        let cards = Card::get_all(&conn).unwrap();
+        let session_id = Session::start(
+            &conn,
+            Session {
+                id: None,
+                profile_name: PROFILE.to_string(),
+                started_at: now_timestamp(),
+                ended_at: None,
+                category_name: Some("Animals".to_string()),
+                mode: SessionMode::RandomPools,
+                question_count: 2,
+                choices_count: 2,
+                correct: 0,
+                incorrect: 0,
+                dont_know: 0,
+                score_delta: 0,
+            },
+        )
+        .unwrap();
+        for (card, answer, score_after) in [
+            (&cards[0], Answer::Correct, 1),
+            (&cards[1], Answer::DontKnow, -1),
+        ] {
+            Review::add(
+                &conn,
+                Review {
+                    id: None,
+                    card_id: card.id.unwrap(),
+                    profile_name: PROFILE.to_string(),
+                    timestamp: now_timestamp(),
+                    chosen_option: None,
+                    answer,
+                    response_time: Duration::from_secs(1),
+                    score_before: 0,
+                    score_after,
+                    session_id: Some(session_id),
+                },
+            )
+            .unwrap();
+        }
+
+        let session = Session::finish(&conn, session_id).unwrap();
+        assert!(session.ended_at.is_some());
+        assert_eq!(
+            (session.correct, session.incorrect, session.dont_know),
+            (1, 0, 1)
+        );
+        assert_eq!(session.score_delta, 0);
+        let missed = Session::get_missed_cards(&conn, session_id).unwrap();
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].id, cards[1].id);
+    }
+
+    #[test]
+    fn media_is_deduplicated_and_kept_while_used() {
+        let conn = setup();
+        let hash = Media::add(&conn, b"png bytes").unwrap();
+        assert_eq!(Media::add(&conn, b"png bytes").unwrap(), hash);
+        let unused = Media::add(&conn, b"other bytes").unwrap();
+
+        let id = Card::get_all(&conn).unwrap()[0].id.unwrap();
+        let mut with_media = card("犬", "dog", 1, "Animals");
+        with_media.front_media = Some(hash.clone());
+        Card::update(&conn, id, with_media).unwrap();
+        assert_eq!(
+            Card::get_by_id(&conn, id).unwrap().front_media,
+            Some(hash.clone())
+        );
+
+        assert_eq!(Media::delete_unused(&conn).unwrap(), 1);
+        assert_eq!(Media::get(&conn, &hash).unwrap().data, b"png bytes");
+        assert!(Media::get(&conn, &unused).is_err());
+    }
+
+    fn vocabulary() -> NoteType {
+        let template = |name: &str, front: &str, back: &str| CardTemplate {
+            name: name.to_string(),
+            front: front.to_string(),
+            back: back.to_string(),
+        };
+        NoteType {
+            name: "Vocabulary".to_string(),
+            fields: vec![
+                "kanji".to_string(),
+                "reading".to_string(),
+                "meaning".to_string(),
+            ],
+            templates: vec![
+                template("meaning", "{{kanji}}", "{{meaning}}"),
+                template("reading", "{{kanji}}", "{{reading}}"),
+                template("kanji", "{{meaning}}", "{{kanji}}"),
+            ],
+        }
+    }
+
+    #[test]
+    fn templates_leave_braces_in_field_values_alone() {
+        let template = CardTemplate {
+            name: "meaning".to_string(),
+            front: "{{kanji}} ({{unknown}})".to_string(),
+            back: "{{meaning}}".to_string(),
+        };
+        let fields = BTreeMap::from([
+            ("kanji".to_string(), "{{meaning}}".to_string()),
+            ("meaning".to_string(), "{{kanji}} {".to_string()),
+        ]);
+        assert_eq!(
+            template.render(&fields),
+            Some((
+                "{{meaning}} ({{unknown}})".to_string(),
+                "{{kanji}} {".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn notes_generate_and_regenerate_cards() {
+        let conn = setup();
+        NoteType::add(&conn, &vocabulary()).unwrap();
+        let fields = |kanji: &str, reading: &str, meaning: &str| {
+            BTreeMap::from([
+                ("kanji".to_string(), kanji.to_string()),
+                ("reading".to_string(), reading.to_string()),
+                ("meaning".to_string(), meaning.to_string()),
+            ])
+        };
+        let id = Note::add(
+            &conn,
+            &Note {
+                id: None,
+                note_type_name: "Vocabulary".to_string(),
+                pool_id: 2,
+                fields: fields("水", "みず", "water"),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            Note::get_in_pool(&conn, 2)
+                .unwrap()
+                .iter()
+                .map(|note| note.id)
+                .collect::<Vec<_>>(),
+            vec![Some(id)]
+        );
+        let cards = Note::get_cards(&conn, id).unwrap();
+        assert_eq!(cards.len(), 3);
+        assert_eq!(cards["reading"].front, "水");
+        assert_eq!(cards["reading"].back, "みず");
+        assert_eq!(cards["kanji"].category_name.as_deref(), Some("Lonely"));
+
+        let reading_id = cards["reading"].id.unwrap();
+        let progress = Progress {
+            score: 2,
+            schedule: Schedule::default(),
+        };
+        Card::set_progress(&conn, PROFILE, reading_id, &progress).unwrap();
+        Note::update_fields(&conn, id, &fields("水", "すい", "")).unwrap();
+        let cards = Note::get_cards(&conn, id).unwrap();
+        // Without a meaning, only the reading card is left, and it kept its progress.
+        assert_eq!(cards.keys().collect::<Vec<_>>(), vec!["reading"]);
+        assert_eq!(cards["reading"].id, Some(reading_id));
+        assert_eq!(cards["reading"].back, "すい");
+        assert_eq!(
+            Card::get_progress(&conn, PROFILE, reading_id).unwrap(),
+            progress
+        );
+
+        let mut note_type = vocabulary();
+        note_type
+            .templates
+            .retain(|template| template.name != "reading");
+        NoteType::update(&conn, &note_type).unwrap();
+        Note::update_fields(&conn, id, &fields("水", "みず", "water")).unwrap();
+        let cards = Note::get_cards(&conn, id).unwrap();
+        assert_eq!(cards.keys().collect::<Vec<_>>(), vec!["kanji", "meaning"]);
+        assert_eq!(Card::get_in_pool(&conn, 2).unwrap().len(), 3);
+
+        Note::delete(&conn, id).unwrap();
+        assert_eq!(Card::get_in_pool(&conn, 2).unwrap().len(), 1);
+    }
 }
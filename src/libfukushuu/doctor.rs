@@ -0,0 +1,422 @@
+use super::db::{self, Card, Category};
+use log::{debug, info};
+use rusqlite::{named_params, Connection, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Something wrong with the database that the quiz or the importer would trip over.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Finding {
+    /// A line reported by `PRAGMA integrity_check`.
+    Corruption(String),
+    /// A row in `table` references a row of `parent` that does not exist.
+    ForeignKeyViolation {
+        table: String,
+        rowid: i64,
+        parent: String,
+    },
+    /// A card whose `categoryName` is not the one of the pool it is in.
+    CategoryMismatch {
+        card_id: i32,
+        card_category: Option<String>,
+        pool_category: Option<String>,
+    },
+    MissingImage {
+        card_id: i32,
+        path: PathBuf,
+    },
+    /// A pool with too few cards to fill every option of a question.
+    SmallPool {
+        pool_id: i32,
+        cards: usize,
+        needed: u32,
+    },
+    /// A category without pools, cards or subcategories.
+    EmptyCategory(String),
+    /// Cards whose fronts are the same once [folded](super::normalize::fold). The cards of one
+    /// note count once, as their templates may share a front.
+    DuplicateFront {
+        front: String,
+        card_ids: Vec<i32>,
+    },
+}
+
+impl Finding {
+    /// Whether [`fix`] can repair this without losing anything. Empty categories are moved
+    /// to the trash, so they can be restored.
+    pub fn is_fixable(&self) -> bool {
+        matches!(
+            self,
+            Finding::CategoryMismatch { .. } | Finding::EmptyCategory(_)
+        )
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::Corruption(msg) => write!(f, "Integrity check failed: {}", msg),
+            Finding::ForeignKeyViolation {
+                table,
+                rowid,
+                parent,
+            } => write!(
+                f,
+                "Row {} of {} references a missing {}",
+                rowid, table, parent
+            ),
+            Finding::CategoryMismatch {
+                card_id,
+                card_category,
+                pool_category,
+            } => write!(
+                f,
+                "Card {} is in category {:?}, but its pool is in {:?}",
+                card_id, card_category, pool_category
+            ),
+            Finding::MissingImage { card_id, path } => {
+                write!(f, "Card {} uses missing image {:?}", card_id, path)
+            }
+            Finding::SmallPool {
+                pool_id,
+                cards,
+                needed,
+            } => write!(
+                f,
+                "Pool {} has {} cards, fewer than the {} choices per question",
+                pool_id, cards, needed
+            ),
+            Finding::EmptyCategory(name) => write!(f, "Category '{}' is empty", name),
+            Finding::DuplicateFront { front, card_ids } => {
+                write!(f, "Cards {:?} share the front {:?}", card_ids, front)
+            }
+        }
+    }
+}
+
+/// Runs every check. `choices_count` is the number of options per question the
+/// quiz will be run with, and relative image paths are looked up in `media_dir`.
+pub fn diagnose(
+    connection: &Connection,
+    choices_count: u32,
+    media_dir: &Path,
+) -> super::Result<Vec<Finding>> {
+    let mut findings = vec![];
+    findings.extend(check_integrity(connection)?);
+    findings.extend(check_foreign_keys(connection)?);
+    findings.extend(check_category_mismatches(connection)?);
+    findings.extend(check_missing_images(connection, media_dir)?);
+    findings.extend(check_small_pools(connection, choices_count)?);
+    findings.extend(check_empty_categories(connection)?);
+    findings.extend(check_duplicate_fronts(connection)?);
+    info!("[DOCTOR] Found {} problems", findings.len());
+
+    Ok(findings)
+}
+
+/// Repairs `finding` if it [is fixable](Finding::is_fixable). Returns whether anything was done.
+pub fn fix(connection: &Connection, finding: &Finding) -> super::Result<bool> {
+    match finding {
+        Finding::CategoryMismatch { card_id, .. } => {
+            connection.execute(
+                "UPDATE Card SET categoryName = (SELECT categoryName FROM Pool WHERE id = Card.poolId) \
+                WHERE id = :id",
+                named_params! {":id": card_id},
+            )?;
+            debug!("[DOCTOR] Moved Card {} to its pool's category", card_id);
+            Ok(true)
+        }
+        Finding::EmptyCategory(name) => {
+            let trash_id = Category::trash(connection, name)?;
+            debug!(
+                "[DOCTOR] Moved empty Category '{}' to trash entry {}",
+                name, trash_id
+            );
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Runs [`diagnose`], then [`fix`]es what can be fixed in one transaction.
+/// Returns every finding along with whether it was fixed.
+pub fn diagnose_and_fix(
+    connection: &Connection,
+    choices_count: u32,
+    media_dir: &Path,
+) -> super::Result<Vec<(Finding, bool)>> {
+    db::atomically(connection, || {
+        diagnose(connection, choices_count, media_dir)?
+            .into_iter()
+            .map(|finding| {
+                let fixed = fix(connection, &finding)?;
+                Ok((finding, fixed))
+            })
+            .collect()
+    })
+}
+
+fn check_integrity(connection: &Connection) -> Result<Vec<Finding>> {
+    let mut statement = connection.prepare("PRAGMA integrity_check")?;
+    let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+
+    Ok(rows
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|msg| msg != "ok")
+        .map(Finding::Corruption)
+        .collect())
+}
+
+fn check_foreign_keys(connection: &Connection) -> Result<Vec<Finding>> {
+    let mut statement = connection.prepare("PRAGMA foreign_key_check")?;
+    let rows = statement.query_map([], |row| {
+        Ok(Finding::ForeignKeyViolation {
+            table: row.get(0)?,
+            rowid: row.get::<_, Option<i64>>(1)?.unwrap_or_default(),
+            parent: row.get(2)?,
+        })
+    })?;
+
+    rows.collect()
+}
+
+fn check_category_mismatches(connection: &Connection) -> Result<Vec<Finding>> {
+    let mut statement = connection.prepare(
+        "SELECT Card.id, Card.categoryName, Pool.categoryName FROM Card \
+        JOIN Pool ON Pool.id = Card.poolId \
+        WHERE Card.categoryName IS NOT Pool.categoryName \
+        AND Card.trashId IS NULL AND Pool.trashId IS NULL",
+    )?;
+    let rows = statement.query_map([], |row| {
+        Ok(Finding::CategoryMismatch {
+            card_id: row.get(0)?,
+            card_category: row.get(1)?,
+            pool_category: row.get(2)?,
+        })
+    })?;
+
+    rows.collect()
+}
+
+fn check_missing_images(connection: &Connection, media_dir: &Path) -> super::Result<Vec<Finding>> {
+    Ok(Card::get_all(connection)?
+        .into_iter()
+        .flat_map(|card| {
+            let card_id = card.id.unwrap_or_default();
+            // Images stored in the database do not need the file anymore.
+            [
+                (card.front_image, card.front_media),
+                (card.back_image, card.back_media),
+            ]
+            .into_iter()
+            .filter(|(path, media)| media.is_none() && !path.as_os_str().is_empty())
+            .map(move |(path, _)| db::resolve_path(media_dir, &path))
+            .filter(|path| !path.exists())
+            .map(move |path| Finding::MissingImage { card_id, path })
+        })
+        .collect())
+}
+
+fn check_small_pools(connection: &Connection, choices_count: u32) -> Result<Vec<Finding>> {
+    let mut statement = connection.prepare(
+        "SELECT Pool.id, COUNT(Card.id) AS cards FROM Pool \
+        LEFT JOIN Card ON Card.poolId = Pool.id AND Card.trashId IS NULL \
+        WHERE Pool.trashId IS NULL \
+        GROUP BY Pool.id HAVING cards < :choicesCount",
+    )?;
+    let rows = statement.query_map(named_params! {":choicesCount": choices_count}, |row| {
+        Ok(Finding::SmallPool {
+            pool_id: row.get(0)?,
+            cards: row.get::<_, i64>(1)? as usize,
+            needed: choices_count,
+        })
+    })?;
+
+    rows.collect()
+}
+
+fn check_empty_categories(connection: &Connection) -> Result<Vec<Finding>> {
+    let mut statement = connection.prepare(
+        "SELECT name FROM Category WHERE trashId IS NULL \
+        AND NOT EXISTS (SELECT 1 FROM Pool \
+            WHERE Pool.categoryName = Category.name AND Pool.trashId IS NULL) \
+        AND NOT EXISTS (SELECT 1 FROM Card \
+            WHERE Card.categoryName = Category.name AND Card.trashId IS NULL) \
+        AND NOT EXISTS (SELECT 1 FROM Category AS child \
+            WHERE child.parentName = Category.name AND child.trashId IS NULL)",
+    )?;
+    let rows = statement.query_map([], |row| Ok(Finding::EmptyCategory(row.get(0)?)))?;
+
+    rows.collect()
+}
+
+fn check_duplicate_fronts(connection: &Connection) -> Result<Vec<Finding>> {
+    let mut statement = connection.prepare(
+        "SELECT min(Card.front), json_group_array(Card.id) FROM Card \
+        LEFT JOIN NoteCard ON NoteCard.cardId = Card.id \
+        WHERE Card.front != '' AND Card.trashId IS NULL GROUP BY Card.frontFolded \
+        HAVING COUNT(DISTINCT coalesce('note ' || NoteCard.noteId, 'card ' || Card.id)) > 1",
+    )?;
+    let rows = statement.query_map([], |row| {
+        let ids: String = row.get(1)?;
+        Ok(Finding::DuplicateFront {
+            front: row.get(0)?,
+            card_ids: serde_json::from_str(&ids).unwrap_or_default(),
+        })
+    })?;
+
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{CardTemplate, Note, NoteType, Pool};
+    use crate::test_support::{card, setup};
+    use std::collections::BTreeMap;
+
+    /// Breaks the fixture in every way the doctor can find, except for the corruptions.
+    fn broken() -> (Connection, i32, i32) {
+        let conn = setup();
+        let dog = Card::get_in_pool(&conn, 1).unwrap()[0].id.unwrap();
+        conn.execute(
+            "UPDATE Card SET categoryName = 'Lonely' WHERE id = ?1",
+            [dog],
+        )
+        .unwrap();
+        let mut missing = card("写真", "photo", 1, "Animals");
+        missing.front_image = PathBuf::from("missing.png");
+        let photo = Card::add(&conn, missing).unwrap();
+        Card::add(&conn, card("猫", "kitty", 1, "Animals")).unwrap();
+        Category::create(&conn, "Empty".to_string(), None, None).unwrap();
+        (conn, dog, photo)
+    }
+
+    #[test]
+    fn every_problem_is_found() {
+        let (conn, dog, photo) = broken();
+        let media_dir = Path::new("/nonexistent");
+        let findings = diagnose(&conn, 4, media_dir).unwrap();
+        let cat_ids: Vec<i32> = Card::get_all(&conn)
+            .unwrap()
+            .iter()
+            .filter(|card| card.front == "猫")
+            .map(|card| card.id.unwrap())
+            .collect();
+
+        assert_eq!(
+            findings,
+            vec![
+                Finding::CategoryMismatch {
+                    card_id: dog,
+                    card_category: Some("Lonely".to_string()),
+                    pool_category: Some("Animals".to_string()),
+                },
+                Finding::MissingImage {
+                    card_id: photo,
+                    path: media_dir.join("missing.png"),
+                },
+                Finding::SmallPool {
+                    pool_id: 2,
+                    cards: 1,
+                    needed: 4,
+                },
+                Finding::EmptyCategory("Empty".to_string()),
+                Finding::DuplicateFront {
+                    front: "猫".to_string(),
+                    card_ids: cat_ids,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cards_of_one_note_are_not_duplicates() {
+        let conn = setup();
+        let template = |name: &str, back: &str| CardTemplate {
+            name: name.to_string(),
+            front: "{{kanji}}".to_string(),
+            back: back.to_string(),
+        };
+        let note_type = NoteType {
+            name: "Vocabulary".to_string(),
+            fields: vec![
+                "kanji".to_string(),
+                "reading".to_string(),
+                "meaning".to_string(),
+            ],
+            templates: vec![
+                template("meaning", "{{meaning}}"),
+                template("reading", "{{reading}}"),
+            ],
+        };
+        NoteType::add(&conn, &note_type).unwrap();
+        let note = Note {
+            id: None,
+            note_type_name: "Vocabulary".to_string(),
+            pool_id: 1,
+            fields: BTreeMap::from([
+                ("kanji".to_string(), "馬".to_string()),
+                ("reading".to_string(), "うま".to_string()),
+                ("meaning".to_string(), "horse".to_string()),
+            ]),
+        };
+        Note::add(&conn, &note).unwrap();
+
+        assert_eq!(
+            diagnose(&conn, 1, Path::new("/nonexistent")).unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn fixes_move_cards_and_trash_empty_categories() {
+        let (conn, dog, _) = broken();
+        let fixed: Vec<_> = diagnose_and_fix(&conn, 4, Path::new("/nonexistent"))
+            .unwrap()
+            .into_iter()
+            .filter(|(_, fixed)| *fixed)
+            .map(|(finding, _)| finding)
+            .collect();
+        assert_eq!(fixed.len(), 2);
+        assert!(fixed.iter().all(Finding::is_fixable));
+
+        assert_eq!(
+            Card::get_by_id(&conn, dog)
+                .unwrap()
+                .category_name
+                .as_deref(),
+            Some("Animals")
+        );
+        assert!(Category::in_trash(&conn, "Empty").unwrap());
+        assert!(diagnose(&conn, 4, Path::new("/nonexistent"))
+            .unwrap()
+            .iter()
+            .all(|finding| !finding.is_fixable()));
+    }
+
+    #[test]
+    fn trashed_items_are_left_alone() {
+        let (conn, dog, photo) = broken();
+        Card::trash(&conn, photo).unwrap();
+        let kitty = Card::get_all(&conn)
+            .unwrap()
+            .into_iter()
+            .find(|card| card.back == "kitty")
+            .unwrap();
+        Card::trash(&conn, kitty.id.unwrap()).unwrap();
+        Pool::trash(&conn, 2).unwrap();
+        Category::trash(&conn, "Empty").unwrap();
+
+        let findings = diagnose(&conn, 4, Path::new("/nonexistent")).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert!(matches!(findings[0], Finding::CategoryMismatch { card_id, .. } if card_id == dog));
+
+        Card::trash(&conn, dog).unwrap();
+        let findings = diagnose(&conn, 4, Path::new("/nonexistent")).unwrap();
+        assert!(!findings
+            .iter()
+            .any(|finding| matches!(finding, Finding::CategoryMismatch { .. })));
+    }
+}
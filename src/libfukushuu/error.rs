@@ -0,0 +1,34 @@
+use super::migrations::MigrationError;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Everything that can keep the library from doing what it was asked to.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("there are no categories yet, add some cards to the database first")]
+    NoCategories,
+    #[error("there is no category named '{0}'")]
+    UnknownCategory(String),
+    #[error("category '{0}' and the categories nested in it have no pools")]
+    NoPools(String),
+    /// Every pool of the category is empty, or has no cards matching the tag filter.
+    #[error("category '{0}' has no cards to ask")]
+    EmptyCategory(String),
+    /// No question could be made, as its pool had no other cards to use as incorrect options.
+    #[error("pool {pool_id} has only {cards} card(s), but questions need {needed} choices")]
+    PoolTooSmall {
+        pool_id: i32,
+        cards: usize,
+        needed: u32,
+    },
+    #[error("card {card_id} uses image {path:?}, which does not exist")]
+    MissingImage { card_id: i32, path: PathBuf },
+    #[error("cannot play sound: {0}")]
+    Audio(String),
+    #[error("cannot open database: {0}")]
+    Migration(#[from] MigrationError),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,673 @@
+use super::db::DEFAULT_PROFILE;
+use super::normalize;
+use log::{debug, info, warn};
+use rusqlite::{params, Connection, DatabaseName};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use thiserror::Error;
+
+type Migration = fn(&Connection) -> rusqlite::Result<()>;
+
+/// Every schema change, in order. `MIGRATIONS[n]` upgrades a database from
+/// `user_version` n to n + 1. Only ever append to this list!
+const MIGRATIONS: &[Migration] = &[
+    v1_initial_schema,
+    v2_review_history,
+    v3_card_schedule,
+    v4_schedulers,
+    v5_tags,
+    v6_category_hierarchy,
+    v7_card_search,
+    v8_folded_card_search,
+    v9_profiles,
+    v10_sessions,
+    v11_media,
+    v12_audio,
+    v13_card_notes,
+    v14_note_types,
+    v15_category_direction,
+    v16_trash,
+];
+
+/// The schema version this binary reads and writes.
+pub const SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32;
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("database is at schema version {found}, but this program only supports up to version {supported}. please update!")]
+    TooNew { found: i32, supported: i32 },
+    #[error("cannot back up database before migrating: {0}")]
+    Backup(rusqlite::Error),
+    #[error("database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<i32> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Brings the database up to [`SCHEMA_VERSION`]. If `src` is given and the
+/// database already holds data, a copy is saved next to it before anything
+/// is changed.
+pub fn migrate(conn: &Connection, src: Option<&Path>) -> Result<(), MigrationError> {
+    let mut version = schema_version(conn)?;
+    if version == 0 && is_legacy(conn)? {
+        info!("[DB MIGRATE] Found database from before schema versioning, marking as version 1");
+        conn.pragma_update(None, "user_version", 1)?;
+        version = 1;
+    }
+
+    if version > SCHEMA_VERSION {
+        return Err(MigrationError::TooNew {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    if version == SCHEMA_VERSION {
+        debug!("[DB MIGRATE] Schema is up to date (version {})", version);
+        return Ok(());
+    }
+
+    if let (Some(src), true) = (src, version > 0) {
+        let backup = backup_path(src, version);
+        info!("[DB MIGRATE] Backing up database to {:?}", backup);
+        conn.backup(DatabaseName::Main, &backup, None)
+            .map_err(MigrationError::Backup)?;
+    }
+
+    let now = Instant::now();
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let target = idx as i32 + 1;
+        info!("[DB MIGRATE] Migrating to version {}", target);
+        let tx = conn.unchecked_transaction()?;
+        if let Err(err) = migration(&tx) {
+            warn!(
+                "[DB MIGRATE] Migration to version {} failed: {}",
+                target, err
+            );
+            return Err(err.into());
+        }
+        tx.pragma_update(None, "user_version", target)?;
+        tx.commit()?;
+    }
+    info!(
+        "[DB MIGRATE] Migrated from version {} to {} in {} ms.",
+        version,
+        SCHEMA_VERSION,
+        now.elapsed().as_millis()
+    );
+
+    Ok(())
+}
+
+fn is_legacy(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'Category')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+fn backup_path(src: &Path, version: i32) -> PathBuf {
+    let mut name = src.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{}.bak", version));
+    src.with_file_name(name)
+}
+
+fn v1_initial_schema(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE Category (
+              name TEXT NOT NULL,
+              PRIMARY KEY (name)
+            )",
+        (),
+    )?;
+    info!("[DB INIT] Created table Category");
+    conn.execute(
+        "CREATE TABLE Pool (
+              id INTEGER NOT NULL PRIMARY KEY,
+              categoryName TEXT,
+              FOREIGN KEY (categoryName) REFERENCES Category(name) ON DELETE SET NULL ON UPDATE CASCADE
+            )", (),
+    )?;
+    info!("[DB INIT] Created table Pool");
+    conn.execute(
+        "CREATE TABLE Card (
+              id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+              front TEXT NOT NULL,
+              back TEXT NOT NULL,
+              frontImage TEXT NOT NULL,
+              backImage TEXT NOT NULL,
+              score INTEGER NOT NULL,
+              poolId INTEGER,
+              categoryName TEXT,
+              FOREIGN KEY (poolId) REFERENCES Pool(id) ON DELETE SET NULL ON UPDATE CASCADE,
+              FOREIGN KEY (categoryName) REFERENCES Category(name) ON DELETE SET NULL ON UPDATE CASCADE
+            )", (),
+    )?;
+    info!("[DB INIT] Created table Card");
+    conn.execute("CREATE INDEX Card_poolId_idx ON Card(poolId)", ())?;
+    info!("[DB INIT] Created index Card_poolId_idx");
+    conn.execute(
+        "CREATE INDEX Card_categoryName_idx ON Card(categoryName)",
+        (),
+    )?;
+    info!("[DB INIT] Created index Card_categoryName_idx");
+    conn.execute(
+        "CREATE INDEX Pool_categoryName_idx ON Pool(categoryName)",
+        (),
+    )?;
+    info!("[DB INIT] Created index Pool_categoryName_idx");
+
+    Ok(())
+}
+
+fn v2_review_history(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE Review (
+              id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+              cardId INTEGER NOT NULL,
+              timestamp INTEGER NOT NULL,
+              chosenOption TEXT,
+              answer TEXT NOT NULL CHECK (answer IN ('correct', 'incorrect', 'dont_know')),
+              responseTimeMs INTEGER NOT NULL,
+              scoreBefore INTEGER NOT NULL,
+              scoreAfter INTEGER NOT NULL,
+              FOREIGN KEY (cardId) REFERENCES Card(id) ON DELETE CASCADE ON UPDATE CASCADE
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table Review");
+    conn.execute("CREATE INDEX Review_cardId_idx ON Review(cardId)", ())?;
+    info!("[DB MIGRATE] Created index Review_cardId_idx");
+
+    Ok(())
+}
+
+fn v3_card_schedule(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE Card ADD COLUMN dueDate INTEGER;
+        ALTER TABLE Card ADD COLUMN intervalDays INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE Card ADD COLUMN easeFactor REAL NOT NULL DEFAULT 2.5;
+        ALTER TABLE Card ADD COLUMN repetitions INTEGER NOT NULL DEFAULT 0;",
+    )?;
+    info!("[DB MIGRATE] Added scheduling columns to Card");
+    conn.execute("CREATE INDEX Card_dueDate_idx ON Card(dueDate)", ())?;
+    info!("[DB MIGRATE] Created index Card_dueDate_idx");
+
+    Ok(())
+}
+
+fn v4_schedulers(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "ALTER TABLE Category ADD COLUMN scheduler TEXT CHECK (scheduler IN ('score', 'sm2', 'fsrs'))",
+        (),
+    )?;
+    info!("[DB MIGRATE] Added scheduler column to Category");
+    conn.execute_batch(
+        "ALTER TABLE Card ADD COLUMN stability REAL;
+        ALTER TABLE Card ADD COLUMN difficulty REAL;
+        ALTER TABLE Card ADD COLUMN lastReview INTEGER;",
+    )?;
+    info!("[DB MIGRATE] Added FSRS columns to Card");
+
+    Ok(())
+}
+
+fn v5_tags(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE Tag (
+              name TEXT NOT NULL,
+              PRIMARY KEY (name)
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table Tag");
+    conn.execute(
+        "CREATE TABLE CardTag (
+              cardId INTEGER NOT NULL,
+              tagName TEXT NOT NULL,
+              PRIMARY KEY (cardId, tagName),
+              FOREIGN KEY (cardId) REFERENCES Card(id) ON DELETE CASCADE ON UPDATE CASCADE,
+              FOREIGN KEY (tagName) REFERENCES Tag(name) ON DELETE CASCADE ON UPDATE CASCADE
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table CardTag");
+    conn.execute("CREATE INDEX CardTag_tagName_idx ON CardTag(tagName)", ())?;
+    info!("[DB MIGRATE] Created index CardTag_tagName_idx");
+
+    Ok(())
+}
+
+fn v6_category_hierarchy(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "ALTER TABLE Category ADD COLUMN parentName TEXT \
+        REFERENCES Category(name) ON DELETE SET NULL ON UPDATE CASCADE",
+        (),
+    )?;
+    info!("[DB MIGRATE] Added parentName column to Category");
+    conn.execute(
+        "CREATE INDEX Category_parentName_idx ON Category(parentName)",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created index Category_parentName_idx");
+
+    Ok(())
+}
+
+fn v7_card_search(conn: &Connection) -> rusqlite::Result<()> {
+    // The trigram tokenizer matches inside words, which Japanese needs since it has no spaces.
+    conn.execute(
+        "CREATE VIRTUAL TABLE CardSearch USING fts5(
+              front,
+              back,
+              content = 'Card',
+              content_rowid = 'id',
+              tokenize = 'trigram'
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table CardSearch");
+    conn.execute_batch(
+        "CREATE TRIGGER Card_search_insert AFTER INSERT ON Card BEGIN
+              INSERT INTO CardSearch(rowid, front, back) VALUES (new.id, new.front, new.back);
+            END;
+        CREATE TRIGGER Card_search_delete AFTER DELETE ON Card BEGIN
+              INSERT INTO CardSearch(CardSearch, rowid, front, back) VALUES ('delete', old.id, old.front, old.back);
+            END;
+        CREATE TRIGGER Card_search_update AFTER UPDATE OF id, front, back ON Card BEGIN
+              INSERT INTO CardSearch(CardSearch, rowid, front, back) VALUES ('delete', old.id, old.front, old.back);
+              INSERT INTO CardSearch(rowid, front, back) VALUES (new.id, new.front, new.back);
+            END;",
+    )?;
+    info!("[DB MIGRATE] Created triggers keeping CardSearch in sync");
+    conn.execute("INSERT INTO CardSearch(CardSearch) VALUES ('rebuild')", ())?;
+    info!("[DB MIGRATE] Indexed existing cards");
+
+    Ok(())
+}
+
+fn v8_folded_card_search(conn: &Connection) -> rusqlite::Result<()> {
+    // Indexes folded text instead of the cards themselves, so it can no longer read from
+    // Card and has to store its own copy. The library writes the folded text next to the
+    // card, so the triggers only copy columns and other clients, such as the sqlite3 shell,
+    // can write to Card too.
+    conn.execute_batch(
+        "DROP TRIGGER Card_search_insert;
+        DROP TRIGGER Card_search_delete;
+        DROP TRIGGER Card_search_update;
+        DROP TABLE CardSearch;",
+    )?;
+    info!("[DB MIGRATE] Dropped table CardSearch");
+    conn.execute_batch(
+        "ALTER TABLE Card ADD COLUMN frontFolded TEXT NOT NULL DEFAULT '';
+        ALTER TABLE Card ADD COLUMN backFolded TEXT NOT NULL DEFAULT '';",
+    )?;
+    info!("[DB MIGRATE] Added frontFolded and backFolded columns to Card");
+    {
+        let mut select = conn.prepare("SELECT id, front, back FROM Card")?;
+        let mut update =
+            conn.prepare("UPDATE Card SET frontFolded = ?2, backFolded = ?3 WHERE id = ?1")?;
+        let cards = select.query_map([], |row| {
+            Ok((
+                row.get::<_, i32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for card in cards {
+            let (id, front, back) = card?;
+            update.execute(params![id, normalize::fold(&front), normalize::fold(&back)])?;
+        }
+    }
+    info!("[DB MIGRATE] Folded existing cards");
+    conn.execute(
+        "CREATE VIRTUAL TABLE CardSearch USING fts5(
+              front,
+              back,
+              tokenize = 'trigram'
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table CardSearch");
+    conn.execute_batch(
+        "CREATE TRIGGER Card_search_insert AFTER INSERT ON Card BEGIN
+              INSERT INTO CardSearch(rowid, front, back) VALUES (new.id, new.frontFolded, new.backFolded);
+            END;
+        CREATE TRIGGER Card_search_delete AFTER DELETE ON Card BEGIN
+              DELETE FROM CardSearch WHERE rowid = old.id;
+            END;
+        CREATE TRIGGER Card_search_update AFTER UPDATE OF id, frontFolded, backFolded ON Card BEGIN
+              UPDATE CardSearch SET rowid = new.id, front = new.frontFolded, back = new.backFolded
+              WHERE rowid = old.id;
+            END;",
+    )?;
+    info!("[DB MIGRATE] Created triggers keeping CardSearch in sync");
+    conn.execute(
+        "INSERT INTO CardSearch(rowid, front, back) SELECT id, frontFolded, backFolded FROM Card",
+        (),
+    )?;
+    info!("[DB MIGRATE] Indexed existing cards");
+
+    Ok(())
+}
+
+fn v9_profiles(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE Profile (
+              name TEXT NOT NULL,
+              createdAt INTEGER NOT NULL,
+              PRIMARY KEY (name)
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table Profile");
+    conn.execute(
+        "INSERT INTO Profile (name, createdAt) VALUES (?1, CAST(strftime('%s', 'now') AS INTEGER))",
+        [DEFAULT_PROFILE],
+    )?;
+    info!("[DB MIGRATE] Created profile '{}'", DEFAULT_PROFILE);
+    conn.execute(
+        "CREATE TABLE CardProgress (
+              profileName TEXT NOT NULL,
+              cardId INTEGER NOT NULL,
+              score INTEGER NOT NULL DEFAULT 0,
+              dueDate INTEGER,
+              intervalDays INTEGER NOT NULL DEFAULT 0,
+              easeFactor REAL NOT NULL DEFAULT 2.5,
+              repetitions INTEGER NOT NULL DEFAULT 0,
+              stability REAL,
+              difficulty REAL,
+              lastReview INTEGER,
+              PRIMARY KEY (profileName, cardId),
+              FOREIGN KEY (profileName) REFERENCES Profile(name) ON DELETE CASCADE ON UPDATE CASCADE,
+              FOREIGN KEY (cardId) REFERENCES Card(id) ON DELETE CASCADE ON UPDATE CASCADE
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table CardProgress");
+    conn.execute_batch(
+        "CREATE INDEX CardProgress_cardId_idx ON CardProgress(cardId);
+        CREATE INDEX CardProgress_dueDate_idx ON CardProgress(profileName, dueDate);",
+    )?;
+    info!("[DB MIGRATE] Created indexes CardProgress_cardId_idx and CardProgress_dueDate_idx");
+
+    // Everything answered so far was answered by the only person using the database.
+    conn.execute(
+        "INSERT INTO CardProgress \
+        SELECT ?1, id, score, dueDate, intervalDays, easeFactor, repetitions, stability, difficulty, lastReview \
+        FROM Card",
+        [DEFAULT_PROFILE],
+    )?;
+    info!(
+        "[DB MIGRATE] Moved card progress to profile '{}'",
+        DEFAULT_PROFILE
+    );
+    conn.execute_batch(
+        "DROP INDEX Card_dueDate_idx;
+        ALTER TABLE Card DROP COLUMN score;
+        ALTER TABLE Card DROP COLUMN dueDate;
+        ALTER TABLE Card DROP COLUMN intervalDays;
+        ALTER TABLE Card DROP COLUMN easeFactor;
+        ALTER TABLE Card DROP COLUMN repetitions;
+        ALTER TABLE Card DROP COLUMN stability;
+        ALTER TABLE Card DROP COLUMN difficulty;
+        ALTER TABLE Card DROP COLUMN lastReview;",
+    )?;
+    info!("[DB MIGRATE] Dropped progress columns from Card");
+
+    // SQLite only allows adding a foreign key column if it defaults to NULL.
+    conn.execute(
+        "ALTER TABLE Review ADD COLUMN profileName TEXT \
+        REFERENCES Profile(name) ON DELETE CASCADE ON UPDATE CASCADE",
+        (),
+    )?;
+    conn.execute("UPDATE Review SET profileName = ?1", [DEFAULT_PROFILE])?;
+    conn.execute(
+        "CREATE INDEX Review_profileName_idx ON Review(profileName)",
+        (),
+    )?;
+    info!("[DB MIGRATE] Added profileName column to Review");
+
+    Ok(())
+}
+
+fn v10_sessions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE Session (
+              id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+              profileName TEXT NOT NULL,
+              startedAt INTEGER NOT NULL,
+              endedAt INTEGER,
+              categoryName TEXT,
+              mode TEXT NOT NULL CHECK (mode IN ('random_pools', 'due_today')),
+              questionCount INTEGER NOT NULL,
+              choicesCount INTEGER NOT NULL,
+              correct INTEGER NOT NULL DEFAULT 0,
+              incorrect INTEGER NOT NULL DEFAULT 0,
+              dontKnow INTEGER NOT NULL DEFAULT 0,
+              scoreDelta INTEGER NOT NULL DEFAULT 0,
+              FOREIGN KEY (profileName) REFERENCES Profile(name) ON DELETE CASCADE ON UPDATE CASCADE,
+              FOREIGN KEY (categoryName) REFERENCES Category(name) ON DELETE SET NULL ON UPDATE CASCADE
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table Session");
+    conn.execute(
+        "CREATE INDEX Session_profileName_idx ON Session(profileName, startedAt)",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created index Session_profileName_idx");
+    conn.execute(
+        "ALTER TABLE Review ADD COLUMN sessionId INTEGER \
+        REFERENCES Session(id) ON DELETE SET NULL ON UPDATE CASCADE",
+        (),
+    )?;
+    conn.execute("CREATE INDEX Review_sessionId_idx ON Review(sessionId)", ())?;
+    info!("[DB MIGRATE] Added sessionId column to Review");
+
+    Ok(())
+}
+
+fn v11_media(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE Media (
+              hash TEXT NOT NULL PRIMARY KEY,
+              data BLOB NOT NULL,
+              createdAt INTEGER NOT NULL
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table Media");
+    conn.execute_batch(
+        "ALTER TABLE Card ADD COLUMN frontMedia TEXT REFERENCES Media(hash) ON DELETE SET NULL ON UPDATE CASCADE;
+        ALTER TABLE Card ADD COLUMN backMedia TEXT REFERENCES Media(hash) ON DELETE SET NULL ON UPDATE CASCADE;
+        CREATE INDEX Card_frontMedia_idx ON Card(frontMedia);
+        CREATE INDEX Card_backMedia_idx ON Card(backMedia);",
+    )?;
+    info!("[DB MIGRATE] Added frontMedia and backMedia columns to Card");
+
+    Ok(())
+}
+
+fn v12_audio(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE Card ADD COLUMN frontAudio TEXT NOT NULL DEFAULT '';
+        ALTER TABLE Card ADD COLUMN backAudio TEXT NOT NULL DEFAULT '';",
+    )?;
+    info!("[DB MIGRATE] Added frontAudio and backAudio columns to Card");
+
+    Ok(())
+}
+
+fn v13_card_notes(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE Card ADD COLUMN reading TEXT NOT NULL DEFAULT '';
+        ALTER TABLE Card ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+        ALTER TABLE Card ADD COLUMN example TEXT NOT NULL DEFAULT '';",
+    )?;
+    info!("[DB MIGRATE] Added reading, notes and example columns to Card");
+
+    Ok(())
+}
+
+fn v14_note_types(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE NoteType (
+              name TEXT NOT NULL PRIMARY KEY,
+              fields TEXT NOT NULL
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table NoteType");
+    conn.execute(
+        "CREATE TABLE CardTemplate (
+              noteTypeName TEXT NOT NULL,
+              name TEXT NOT NULL,
+              front TEXT NOT NULL,
+              back TEXT NOT NULL,
+              position INTEGER NOT NULL,
+              PRIMARY KEY (noteTypeName, name),
+              FOREIGN KEY (noteTypeName) REFERENCES NoteType(name) ON DELETE CASCADE ON UPDATE CASCADE
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table CardTemplate");
+    conn.execute(
+        "CREATE TABLE Note (
+              id INTEGER PRIMARY KEY,
+              noteTypeName TEXT NOT NULL,
+              poolId INTEGER NOT NULL,
+              fields TEXT NOT NULL,
+              FOREIGN KEY (noteTypeName) REFERENCES NoteType(name) ON DELETE CASCADE ON UPDATE CASCADE,
+              FOREIGN KEY (poolId) REFERENCES Pool(id) ON DELETE CASCADE ON UPDATE CASCADE
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table Note");
+    conn.execute(
+        "CREATE TABLE NoteCard (
+              cardId INTEGER NOT NULL PRIMARY KEY,
+              noteId INTEGER NOT NULL,
+              templateName TEXT NOT NULL,
+              UNIQUE (noteId, templateName),
+              FOREIGN KEY (cardId) REFERENCES Card(id) ON DELETE CASCADE ON UPDATE CASCADE,
+              FOREIGN KEY (noteId) REFERENCES Note(id) ON DELETE CASCADE ON UPDATE CASCADE
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table NoteCard");
+
+    Ok(())
+}
+
+fn v15_category_direction(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("ALTER TABLE Category ADD COLUMN direction TEXT", ())?;
+    info!("[DB MIGRATE] Added direction column to Category");
+
+    Ok(())
+}
+
+fn v16_trash(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE Trash (
+              id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+              kind TEXT NOT NULL,
+              itemKey TEXT NOT NULL,
+              deletedAt INTEGER NOT NULL
+            )",
+        (),
+    )?;
+    info!("[DB MIGRATE] Created table Trash");
+    // Restoring an entry deletes it, which takes everything it trashed out of the trash.
+    for table in ["Category", "Pool", "Card"] {
+        conn.execute(
+            &format!(
+                "ALTER TABLE {table} ADD COLUMN trashId INTEGER \
+                REFERENCES Trash(id) ON DELETE SET NULL"
+            ),
+            (),
+        )?;
+        conn.execute(
+            &format!("CREATE INDEX {table}_trashId_idx ON {table}(trashId)"),
+            (),
+        )?;
+    }
+    info!("[DB MIGRATE] Added trashId columns to Category, Pool and Card");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{self, Card};
+
+    /// A database as written before schema versioning: the first schema, `user_version` 0.
+    fn legacy() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        db::configure(&conn).unwrap();
+        v1_initial_schema(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Category (name) VALUES ('N5');
+            INSERT INTO Pool (id, categoryName) VALUES (1, 'N5');
+            INSERT INTO Card (id, front, back, frontImage, backImage, score, poolId, categoryName)
+                VALUES (7, 'ラーメン', 'ramen', '', '', 3, 1, 'N5');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn legacy_databases_are_migrated_keeping_their_cards() {
+        let conn = legacy();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        migrate(&conn, None).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        let card = Card::get_by_id(&conn, 7).unwrap();
+        assert_eq!((card.front.as_str(), card.pool_id), ("ラーメン", Some(1)));
+        // The score moved to the default profile's progress, and the card is searchable.
+        assert_eq!(
+            Card::get_progress(&conn, DEFAULT_PROFILE, 7).unwrap().score,
+            3
+        );
+        assert_eq!(Card::search(&conn, "らあめん", 10).unwrap().len(), 1);
+
+        // Migrating again is a no-op.
+        migrate(&conn, None).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn empty_databases_get_the_whole_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::configure(&conn).unwrap();
+        migrate(&conn, None).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert!(Card::get_all(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn newer_databases_are_refused() {
+        let conn = legacy();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+
+        assert!(matches!(
+            migrate(&conn, None),
+            Err(MigrationError::TooNew { found, supported })
+                if found == SCHEMA_VERSION + 1 && supported == SCHEMA_VERSION
+        ));
+    }
+
+    #[test]
+    fn backups_are_named_after_the_version() {
+        assert_eq!(
+            backup_path(Path::new("decks/flashcards.db"), 9),
+            Path::new("decks/flashcards.db.v9.bak")
+        );
+    }
+}
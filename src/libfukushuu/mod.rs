@@ -1,2 +1,23 @@
+//! Flashcard storage and quizzing for 日本語復習しよう！.
+//!
+//! [`db`] holds the models and their queries, [`shitsumon`] turns cards into questions and
+//! records the answers, and [`scheduler`] decides how progress changes after each answer.
+//! Open a database with [`db::create_or_open`], or [`db::open_in_memory`] for a throwaway one.
+//! [`worker::Worker`] runs the database work on a thread of its own, and [`audio`] plays the
+//! sound clips of cards.
+
+pub mod audio;
 pub mod db;
-pub mod shitsumon;
\ No newline at end of file
+pub mod doctor;
+pub mod error;
+pub mod migrations;
+pub mod normalize;
+pub mod scheduler;
+pub mod shitsumon;
+pub mod stats;
+pub mod worker;
+
+#[cfg(test)]
+pub(crate) mod test_support;
+
+pub use error::{Error, Result};
@@ -0,0 +1,211 @@
+use unicode_normalization::UnicodeNormalization;
+
+const LONG_VOWEL_MARK: char = 'ー';
+
+/// Kana grouped by the vowel they end in, for spelling out `ー`.
+const VOWEL_ROWS: [(char, &str); 5] = [
+    ('あ', "あぁかがさざただなはばぱまやゃらわゎ"),
+    ('い', "いぃきぎしじちぢにひびぴみりゐ"),
+    ('う', "うぅくぐすずつづぬふぶぷむゆゅるゔ"),
+    ('え', "えぇけげせぜてでねへべぺめれゑ"),
+    ('お', "おぉこごそぞとどのほぼぽもよょろを"),
+];
+
+/// Folds `text` so that spellings which read the same compare equal: NFKC
+/// (which also folds full-width and half-width forms), lowercase, katakana as
+/// hiragana and `ー` spelled out as the vowel it lengthens.
+///
+/// `fold("ラーメン") == fold("らあめん")` and `fold("ＡＢＣ") == fold("abc")`.
+pub fn fold(text: &str) -> String {
+    let text: String = text.nfkc().flat_map(char::to_lowercase).collect();
+    expand_long_vowels(&to_hiragana(&text))
+}
+
+/// Like [`fold`], but reads latin letters as romaji first, so `fold_reading("nihon") == fold("ニホン")`.
+pub fn fold_reading(text: &str) -> String {
+    let text: String = text.nfkc().flat_map(char::to_lowercase).collect();
+    expand_long_vowels(&to_hiragana(&romaji_to_kana(&text)))
+}
+
+/// Whether `a` and `b` are the same word, ignoring script, width and romaji. Latin letters
+/// are only read as romaji against kana, so English words like "fun" and "hun" stay apart.
+pub fn equivalent(a: &str, b: &str) -> bool {
+    let (a, b) = (fold(a), fold(b));
+    a == b || ((has_kana(&a) || has_kana(&b)) && fold_reading(&a) == fold_reading(&b))
+}
+
+/// Whether folded `text` has any kana in it.
+fn has_kana(text: &str) -> bool {
+    text.chars().any(|c| matches!(c, 'ぁ'..='ゖ'))
+}
+
+/// The folded forms to look `query` up by, without duplicates.
+pub fn search_terms(query: &str) -> Vec<String> {
+    let mut terms = vec![fold(query)];
+    let reading = fold_reading(query);
+    if !terms.contains(&reading) {
+        terms.push(reading);
+    }
+    terms
+}
+
+pub fn to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+fn expand_long_vowels(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        let vowel = match c {
+            LONG_VOWEL_MARK => out.chars().last().and_then(vowel_of),
+            _ => None,
+        };
+        out.push(vowel.unwrap_or(c));
+    }
+    out
+}
+
+fn vowel_of(kana: char) -> Option<char> {
+    VOWEL_ROWS
+        .iter()
+        .find(|(_, row)| row.contains(kana))
+        .map(|(vowel, _)| *vowel)
+}
+
+/// Converts lowercase Hepburn (and most Kunrei) romaji to hiragana. Letters that
+/// are not part of a syllable are kept as they are.
+pub fn romaji_to_kana(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut idx = 0;
+    while idx < chars.len() {
+        let c = chars[idx];
+        let next = chars.get(idx + 1).copied();
+
+        // `n` before a consonant (including another `n`), before `'` or at the end is ん.
+        if c == 'n' && !matches!(next, Some('a' | 'i' | 'u' | 'e' | 'o' | 'y')) {
+            out.push('ん');
+            idx += if next == Some('\'') { 2 } else { 1 };
+            continue;
+        }
+        // Doubled consonants are a small っ.
+        if next == Some(c) && c.is_ascii_lowercase() && !"aiueon".contains(c) {
+            out.push('っ');
+            idx += 1;
+            continue;
+        }
+        if c == '-'
+            && out
+                .chars()
+                .last()
+                .is_some_and(|last| vowel_of(last).is_some())
+        {
+            out.push(LONG_VOWEL_MARK);
+            idx += 1;
+            continue;
+        }
+
+        let syllable = (1..=3).rev().find_map(|len| {
+            let end = idx + len;
+            if end > chars.len() {
+                return None;
+            }
+            let key: String = chars[idx..end].iter().collect();
+            syllable(&key).map(|kana| (kana, len))
+        });
+        match syllable {
+            Some((kana, len)) => {
+                out.push_str(kana);
+                idx += len;
+            }
+            None => {
+                out.push(c);
+                idx += 1;
+            }
+        }
+    }
+    out
+}
+
+#[rustfmt::skip]
+fn syllable(romaji: &str) -> Option<&'static str> {
+    Some(match romaji {
+        "a" => "あ", "i" => "い", "u" => "う", "e" => "え", "o" => "お",
+        "ka" => "か", "ki" => "き", "ku" => "く", "ke" => "け", "ko" => "こ",
+        "ga" => "が", "gi" => "ぎ", "gu" => "ぐ", "ge" => "げ", "go" => "ご",
+        "sa" => "さ", "shi" | "si" => "し", "su" => "す", "se" => "せ", "so" => "そ",
+        "za" => "ざ", "ji" | "zi" => "じ", "zu" => "ず", "ze" => "ぜ", "zo" => "ぞ",
+        "ta" => "た", "chi" | "ti" => "ち", "tsu" | "tu" => "つ", "te" => "て", "to" => "と",
+        "da" => "だ", "di" => "ぢ", "du" => "づ", "de" => "で", "do" => "ど",
+        "na" => "な", "ni" => "に", "nu" => "ぬ", "ne" => "ね", "no" => "の",
+        "ha" => "は", "hi" => "ひ", "fu" | "hu" => "ふ", "he" => "へ", "ho" => "ほ",
+        "ba" => "ば", "bi" => "び", "bu" => "ぶ", "be" => "べ", "bo" => "ぼ",
+        "pa" => "ぱ", "pi" => "ぴ", "pu" => "ぷ", "pe" => "ぺ", "po" => "ぽ",
+        "ma" => "ま", "mi" => "み", "mu" => "む", "me" => "め", "mo" => "も",
+        "ya" => "や", "yu" => "ゆ", "yo" => "よ",
+        "ra" => "ら", "ri" => "り", "ru" => "る", "re" => "れ", "ro" => "ろ",
+        "wa" => "わ", "wo" => "を",
+        "kya" => "きゃ", "kyu" => "きゅ", "kyo" => "きょ",
+        "gya" => "ぎゃ", "gyu" => "ぎゅ", "gyo" => "ぎょ",
+        "sha" | "sya" => "しゃ", "shu" | "syu" => "しゅ", "she" => "しぇ", "sho" | "syo" => "しょ",
+        "ja" | "jya" | "zya" => "じゃ", "ju" | "jyu" | "zyu" => "じゅ", "je" => "じぇ", "jo" | "jyo" | "zyo" => "じょ",
+        "cha" | "tya" => "ちゃ", "chu" | "tyu" => "ちゅ", "che" => "ちぇ", "cho" | "tyo" => "ちょ",
+        "nya" => "にゃ", "nyu" => "にゅ", "nyo" => "にょ",
+        "hya" => "ひゃ", "hyu" => "ひゅ", "hyo" => "ひょ",
+        "bya" => "びゃ", "byu" => "びゅ", "byo" => "びょ",
+        "pya" => "ぴゃ", "pyu" => "ぴゅ", "pyo" => "ぴょ",
+        "mya" => "みゃ", "myu" => "みゅ", "myo" => "みょ",
+        "rya" => "りゃ", "ryu" => "りゅ", "ryo" => "りょ",
+        "fa" => "ふぁ", "fi" => "ふぃ", "fe" => "ふぇ", "fo" => "ふぉ",
+        "va" => "ゔぁ", "vi" => "ゔぃ", "vu" => "ゔ", "ve" => "ゔぇ", "vo" => "ゔぉ",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_and_romaji_are_equivalent() {
+        assert!(equivalent("にほん", "ニホン"));
+        assert!(equivalent("nihon", "にほん"));
+        assert!(equivalent("ﾆﾎﾝ", "ニホン"));
+        assert!(!equivalent("にほん", "にっぽん"));
+    }
+
+    #[test]
+    fn english_is_not_read_as_romaji() {
+        assert!(!equivalent("fun", "hun"));
+        assert!(!equivalent("sit", "shit"));
+        assert!(equivalent("Dog", "dog"));
+        assert!(equivalent("hun", "ふん"));
+    }
+
+    #[test]
+    fn width_and_nfkc_variants_fold() {
+        assert_eq!(fold("ＡＢＣ１２３"), "abc123");
+        assert_eq!(fold("㌔"), fold("キロ"));
+    }
+
+    #[test]
+    fn long_vowels_are_spelled_out() {
+        assert_eq!(fold("ラーメン"), "らあめん");
+        assert_eq!(fold_reading("ra-men"), "らあめん");
+        assert_eq!(fold("ー"), "ー");
+    }
+
+    #[test]
+    fn romaji_edge_cases() {
+        assert_eq!(romaji_to_kana("konnichiha"), "こんにちは");
+        assert_eq!(romaji_to_kana("kitte"), "きって");
+        assert_eq!(romaji_to_kana("kan'i"), "かんい");
+        assert_eq!(romaji_to_kana("shinbun"), "しんぶん");
+        assert_eq!(romaji_to_kana("kyouto"), "きょうと");
+    }
+}
@@ -0,0 +1,236 @@
+use super::{next_score, Progress, Scheduler, SECONDS_PER_DAY};
+use crate::db::{Answer, Schedule};
+use std::time::Duration;
+
+const DECAY: f64 = -0.5;
+const FACTOR: f64 = 19.0 / 81.0;
+
+/// FSRS-4.5 default weights, fitted on a large collection of Anki reviews.
+const DEFAULT_WEIGHTS: [f64; 17] = [
+    0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461, 2.1072,
+    0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Grade {
+    Again = 1,
+    Hard = 2,
+    Good = 3,
+    Easy = 4,
+}
+
+impl Grade {
+    /// Multiple choice has no self-rating, so correct answers are graded by how long they took.
+    fn from_answer(answer: Answer, response_time: Duration) -> Grade {
+        match answer {
+            Answer::Correct if response_time <= Duration::from_secs(4) => Grade::Easy,
+            Answer::Correct if response_time <= Duration::from_secs(10) => Grade::Good,
+            Answer::Correct => Grade::Hard,
+            Answer::Incorrect | Answer::DontKnow => Grade::Again,
+        }
+    }
+}
+
+/// The Free Spaced Repetition Scheduler (FSRS-4.5).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fsrs {
+    pub weights: [f64; 17],
+    /// Probability of remembering a card when it comes due, between 0 and 1.
+    pub desired_retention: f64,
+    /// Longest interval in days.
+    pub maximum_interval: i32,
+}
+
+impl Default for Fsrs {
+    fn default() -> Self {
+        Fsrs {
+            weights: DEFAULT_WEIGHTS,
+            desired_retention: 0.9,
+            maximum_interval: 36500,
+        }
+    }
+}
+
+impl Scheduler for Fsrs {
+    fn review(
+        &self,
+        progress: Progress,
+        answer: Answer,
+        response_time: Duration,
+        now: i64,
+    ) -> Progress {
+        let grade = Grade::from_answer(answer, response_time);
+        let schedule = progress.schedule;
+        let (stability, difficulty) = match (
+            schedule.stability,
+            schedule.difficulty,
+            schedule.last_review,
+        ) {
+            (Some(stability), Some(difficulty), Some(last_review)) => {
+                let elapsed_days = (now - last_review).max(0) as f64 / SECONDS_PER_DAY as f64;
+                let retrievability = retrievability(elapsed_days, stability);
+                let next_stability = if grade == Grade::Again {
+                    self.forget_stability(difficulty, stability, retrievability)
+                } else {
+                    self.recall_stability(difficulty, stability, retrievability, grade)
+                };
+                (next_stability, self.next_difficulty(difficulty, grade))
+            }
+            _ => (
+                self.initial_stability(grade),
+                self.initial_difficulty(grade),
+            ),
+        };
+        let interval = self.interval(stability);
+
+        Progress {
+            score: next_score(progress.score, answer),
+            schedule: Schedule {
+                due: Some(now + interval as i64 * SECONDS_PER_DAY),
+                interval,
+                repetitions: if grade == Grade::Again {
+                    0
+                } else {
+                    schedule.repetitions + 1
+                },
+                stability: Some(stability),
+                difficulty: Some(difficulty),
+                last_review: Some(now),
+                ..schedule
+            },
+        }
+    }
+}
+
+impl Fsrs {
+    fn w(&self, idx: usize) -> f64 {
+        self.weights[idx]
+    }
+
+    fn initial_stability(&self, grade: Grade) -> f64 {
+        self.w(grade as usize - 1).max(0.1)
+    }
+
+    fn initial_difficulty(&self, grade: Grade) -> f64 {
+        (self.w(4) - (grade as i32 - 3) as f64 * self.w(5)).clamp(1.0, 10.0)
+    }
+
+    fn next_difficulty(&self, difficulty: f64, grade: Grade) -> f64 {
+        let next = difficulty - self.w(6) * (grade as i32 - 3) as f64;
+        let reverted = self.w(7) * self.initial_difficulty(Grade::Good) + (1.0 - self.w(7)) * next;
+        reverted.clamp(1.0, 10.0)
+    }
+
+    fn recall_stability(
+        &self,
+        difficulty: f64,
+        stability: f64,
+        retrievability: f64,
+        grade: Grade,
+    ) -> f64 {
+        let hard_penalty = if grade == Grade::Hard {
+            self.w(15)
+        } else {
+            1.0
+        };
+        let easy_bonus = if grade == Grade::Easy {
+            self.w(16)
+        } else {
+            1.0
+        };
+        stability
+            * (1.0
+                + self.w(8).exp()
+                    * (11.0 - difficulty)
+                    * stability.powf(-self.w(9))
+                    * (((1.0 - retrievability) * self.w(10)).exp() - 1.0)
+                    * hard_penalty
+                    * easy_bonus)
+    }
+
+    fn forget_stability(&self, difficulty: f64, stability: f64, retrievability: f64) -> f64 {
+        self.w(11)
+            * difficulty.powf(-self.w(12))
+            * ((stability + 1.0).powf(self.w(13)) - 1.0)
+            * ((1.0 - retrievability) * self.w(14)).exp()
+    }
+
+    fn interval(&self, stability: f64) -> i32 {
+        let days = stability / FACTOR * (self.desired_retention.powf(1.0 / DECAY) - 1.0);
+        (days.round() as i32).clamp(1, self.maximum_interval.max(1))
+    }
+}
+
+/// Probability of recalling a card `elapsed_days` after its last review.
+fn retrievability(elapsed_days: f64, stability: f64) -> f64 {
+    (1.0 + FACTOR * elapsed_days / stability).powf(DECAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn review(fsrs: &Fsrs, schedule: Schedule, answer: Answer, seconds: u64, now: i64) -> Schedule {
+        let progress = Progress { score: 0, schedule };
+        fsrs.review(progress, answer, Duration::from_secs(seconds), now)
+            .schedule
+    }
+
+    #[test]
+    fn first_reviews_start_from_the_weights() {
+        let fsrs = Fsrs::default();
+        let easy = review(&fsrs, Schedule::default(), Answer::Correct, 2, NOW);
+        assert_eq!(easy.stability, Some(DEFAULT_WEIGHTS[3]));
+        // At 90% retention a card comes due after its stability in days.
+        assert_eq!(easy.interval, 14);
+        assert_eq!(easy.due, Some(NOW + 14 * SECONDS_PER_DAY));
+        assert_eq!(easy.repetitions, 1);
+
+        let again = review(&fsrs, Schedule::default(), Answer::DontKnow, 2, NOW);
+        assert_eq!(again.stability, Some(DEFAULT_WEIGHTS[0]));
+        assert_eq!(again.interval, 1);
+        assert_eq!(again.repetitions, 0);
+        assert!(again.difficulty > easy.difficulty);
+    }
+
+    #[test]
+    fn recalling_grows_stability_and_forgetting_shrinks_it() {
+        let fsrs = Fsrs::default();
+        let good = review(&fsrs, Schedule::default(), Answer::Correct, 8, NOW);
+        let due = good.due.unwrap();
+
+        let recalled = review(&fsrs, good, Answer::Correct, 8, due);
+        assert!(recalled.stability > good.stability);
+        assert!(recalled.interval > good.interval);
+        let forgotten = review(&fsrs, good, Answer::Incorrect, 8, due);
+        assert!(forgotten.stability < good.stability);
+        assert_eq!(forgotten.repetitions, 0);
+    }
+
+    #[test]
+    fn intervals_follow_retention_and_the_maximum() {
+        let fsrs = Fsrs::default();
+        assert!((retrievability(10.0, 10.0) - 0.9).abs() < 1e-9);
+        assert_eq!(fsrs.interval(10.0), 10);
+        let relaxed = Fsrs {
+            desired_retention: 0.8,
+            ..Fsrs::default()
+        };
+        assert!(relaxed.interval(10.0) > 10);
+        let capped = Fsrs {
+            maximum_interval: 30,
+            ..Fsrs::default()
+        };
+        assert_eq!(capped.interval(1000.0), 30);
+    }
+
+    #[test]
+    fn difficulty_stays_between_one_and_ten() {
+        let fsrs = Fsrs::default();
+        assert_eq!(fsrs.next_difficulty(10.0, Grade::Again), 10.0);
+        assert_eq!(fsrs.next_difficulty(1.0, Grade::Easy), 1.0);
+        assert!(fsrs.next_difficulty(5.0, Grade::Again) > 5.0);
+    }
+}
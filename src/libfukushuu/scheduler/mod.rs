@@ -0,0 +1,127 @@
+use super::db::{Answer, Schedule};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod fsrs;
+mod sm2;
+
+pub use fsrs::Fsrs;
+pub use sm2::Sm2;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Everything a scheduler knows about a card.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub score: i32,
+    pub schedule: Schedule,
+}
+
+/// Decides how a card's progress changes after it is answered.
+pub trait Scheduler: Debug + Send + Sync {
+    fn review(
+        &self,
+        progress: Progress,
+        answer: Answer,
+        response_time: Duration,
+        now: i64,
+    ) -> Progress;
+}
+
+/// The original behavior: +1 for a correct answer, -1 otherwise, and no due dates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScoreScheduler;
+
+impl Scheduler for ScoreScheduler {
+    fn review(&self, progress: Progress, answer: Answer, _: Duration, _: i64) -> Progress {
+        Progress {
+            score: next_score(progress.score, answer),
+            schedule: progress.schedule,
+        }
+    }
+}
+
+fn next_score(score: i32, answer: Answer) -> i32 {
+    match answer {
+        Answer::Correct => score + 1,
+        Answer::Incorrect | Answer::DontKnow => score - 1,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedulerKind {
+    Score,
+    #[default]
+    Sm2,
+    Fsrs,
+}
+
+impl SchedulerKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SchedulerKind::Score => "score",
+            SchedulerKind::Sm2 => "sm2",
+            SchedulerKind::Fsrs => "fsrs",
+        }
+    }
+}
+impl std::fmt::Display for SchedulerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl FromStr for SchedulerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "score" => Ok(SchedulerKind::Score),
+            "sm2" => Ok(SchedulerKind::Sm2),
+            "fsrs" => Ok(SchedulerKind::Fsrs),
+            other => Err(format!(
+                "unknown scheduler '{other}' (expected score, sm2 or fsrs)"
+            )),
+        }
+    }
+}
+impl ToSql for SchedulerKind {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+impl FromSql for SchedulerKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|_| FromSqlError::InvalidType)
+    }
+}
+
+/// Picks the scheduler for each card.
+#[derive(Debug, Clone, Default)]
+pub struct SchedulerConfig {
+    /// Used for every card, ignoring what its category is set to.
+    pub forced: Option<SchedulerKind>,
+    pub fsrs: Fsrs,
+}
+
+impl SchedulerConfig {
+    /// The forced scheduler if there is one, else the category's, else the default.
+    pub fn resolve(&self, category_scheduler: Option<SchedulerKind>) -> SchedulerKind {
+        self.forced.or(category_scheduler).unwrap_or_default()
+    }
+
+    pub fn build(&self, kind: SchedulerKind) -> Arc<dyn Scheduler> {
+        match kind {
+            SchedulerKind::Score => Arc::new(ScoreScheduler),
+            SchedulerKind::Sm2 => Arc::new(Sm2),
+            SchedulerKind::Fsrs => Arc::new(self.fsrs.clone()),
+        }
+    }
+}
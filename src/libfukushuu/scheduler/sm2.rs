@@ -0,0 +1,110 @@
+use super::{next_score, Progress, Scheduler, SECONDS_PER_DAY};
+use crate::db::{Answer, Schedule};
+use std::time::Duration;
+
+const MIN_EASE_FACTOR: f64 = 1.3;
+
+/// The SuperMemo-2 algorithm.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sm2;
+
+impl Scheduler for Sm2 {
+    fn review(
+        &self,
+        progress: Progress,
+        answer: Answer,
+        response_time: Duration,
+        now: i64,
+    ) -> Progress {
+        Progress {
+            score: next_score(progress.score, answer),
+            schedule: review(progress.schedule, quality(answer, response_time), now),
+        }
+    }
+}
+
+/// Grades an answer on SM-2's 0-5 scale. Multiple choice has no self-rating,
+/// so correct answers are graded by how long they took.
+fn quality(answer: Answer, response_time: Duration) -> u8 {
+    match answer {
+        Answer::Correct if response_time <= Duration::from_secs(4) => 5,
+        Answer::Correct if response_time <= Duration::from_secs(10) => 4,
+        Answer::Correct => 3,
+        Answer::Incorrect => 1,
+        Answer::DontKnow => 0,
+    }
+}
+
+/// Computes the next schedule of a card answered at `now` with the given `quality`.
+fn review(schedule: Schedule, quality: u8, now: i64) -> Schedule {
+    let quality = quality.min(5);
+    let (interval, repetitions) = if quality >= 3 {
+        let interval = match schedule.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (schedule.interval as f64 * schedule.ease_factor).round() as i32,
+        };
+        (interval, schedule.repetitions + 1)
+    } else {
+        (1, 0)
+    };
+
+    let miss = (5 - quality) as f64;
+    let ease_factor =
+        (schedule.ease_factor + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE_FACTOR);
+
+    Schedule {
+        due: Some(now + interval as i64 * SECONDS_PER_DAY),
+        interval,
+        ease_factor,
+        repetitions,
+        last_review: Some(now),
+        ..schedule
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn intervals_grow_by_the_ease_factor() {
+        let first = review(Schedule::default(), 5, NOW);
+        assert_eq!((first.interval, first.repetitions), (1, 1));
+        assert_eq!(first.due, Some(NOW + SECONDS_PER_DAY));
+        assert!((first.ease_factor - 2.6).abs() < 1e-9);
+
+        let second = review(first, 5, NOW);
+        assert_eq!((second.interval, second.repetitions), (6, 2));
+        let third = review(second, 4, NOW);
+        // 6 days times the ease factor of 2.7 after two perfect answers.
+        assert_eq!((third.interval, third.repetitions), (16, 3));
+        assert!((third.ease_factor - 2.7).abs() < 1e-9);
+        assert_eq!(third.last_review, Some(NOW));
+    }
+
+    #[test]
+    fn failures_start_over_and_ease_bottoms_out() {
+        let learned = Schedule {
+            interval: 20,
+            repetitions: 4,
+            ease_factor: 1.4,
+            ..Schedule::default()
+        };
+        let failed = review(learned, 0, NOW);
+        assert_eq!((failed.interval, failed.repetitions), (1, 0));
+        assert_eq!(failed.ease_factor, MIN_EASE_FACTOR);
+        assert_eq!(failed.due, Some(NOW + SECONDS_PER_DAY));
+    }
+
+    #[test]
+    fn correct_answers_are_graded_by_response_time() {
+        assert_eq!(quality(Answer::Correct, Duration::from_secs(2)), 5);
+        assert_eq!(quality(Answer::Correct, Duration::from_secs(8)), 4);
+        assert_eq!(quality(Answer::Correct, Duration::from_secs(30)), 3);
+        assert_eq!(quality(Answer::Incorrect, Duration::from_secs(2)), 1);
+        assert_eq!(quality(Answer::DontKnow, Duration::from_secs(2)), 0);
+    }
+}
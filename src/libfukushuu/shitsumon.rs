@@ -1,28 +1,23 @@
-use crate::libfukushuu::db::{Card, Pool};
+use crate::db::{
+    self, now_timestamp, Answer, Card, Note, Pool, Review, Session, SessionMode, TagFilter,
+};
 use log::{debug, warn};
-use rand::rng;
 use rand::seq::{IndexedRandom, SliceRandom};
-use rusqlite::{Connection, Result};
-use std::path::PathBuf;
-use std::process::exit;
-use std::time::Instant;
+use rand::{rng, Rng};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use super::db::Category;
+use super::normalize;
+use super::scheduler::{Scheduler, SchedulerConfig};
+use super::{Error, Result};
 
-macro_rules! fetch_pool_cards_and_cache {
-    ($conn:expr, $pool_id:expr, $cached_pool_id:expr) => {
-        match Card::get_in_pool($conn, $pool_id) {
-            Ok(cards) => {
-                $cached_pool_id = Some($pool_id);
-                Some(cards)
-            }
-            Err(_) => {
-                warn!("[Setup] Cannot fetch cards in pool {}", $pool_id);
-                None
-            }
-        }
-    };
-}
 macro_rules! extract_or_continue {
     ($field:expr, $warn_msg:expr $(, $args:expr)*) => {
         match $field {
@@ -36,29 +31,122 @@ macro_rules! extract_or_continue {
 }
 
 macro_rules! card_face_tuple {
-    ($val1:expr, $val2:expr) => {
+    ($text:expr, $path:expr, $media:expr, $media_dir:expr) => {
         OptionPair::from((
-            if $val1.is_empty() { None } else { Some($val1) },
-            if $val2.as_os_str().is_empty() {
-                None
-            } else {
-                Some($val2)
+            if $text.is_empty() { None } else { Some($text) },
+            match $media {
+                Some(hash) => Some(Image::Media(hash)),
+                None if $path.as_os_str().is_empty() => None,
+                None => Some(Image::File(db::resolve_path($media_dir, &$path))),
             },
         ))
     };
 }
 
+/// A card asked as a multiple-choice question: its front, its back as the correct option
+/// and the backs of other cards in its pool as incorrect ones, or the other way round if it
+/// is [`reversed`](Question::reversed).
 #[derive(Debug, Clone)]
-pub(crate) struct Question {
+pub struct Question {
     pub card_id: i32,
+    /// The profile answering the question.
+    pub profile_name: String,
+    /// The quiz the question is asked in, once it has been started.
+    pub session_id: Option<i64>,
+    /// The profile's score on the card, updated by [`Question::record_answer`].
     pub score: i32,
+    pub scheduler: Arc<dyn Scheduler>,
     pub front: OptionPair,
     pub correct_option: OptionPair,
     pub incorrect_options: Vec<OptionPair>,
+    /// Whether the card is asked back to front: `front` is the card's back and the options
+    /// are fronts.
+    pub reversed: bool,
+    /// The card's sound clips, resolved against the media directory. The back's gives the
+    /// answer away, so it is only for after answering.
+    pub front_audio: Option<PathBuf>,
+    pub back_audio: Option<PathBuf>,
+    /// Shown once the question is answered, so that a wrong answer still teaches something.
+    pub reading: Option<String>,
+    pub notes: Option<String>,
+    pub example: Option<String>,
+}
+
+/// Which side of a card is shown, and which one is asked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Show the front, ask for the back.
+    #[default]
+    Forward,
+    /// Show the back, ask for the front.
+    Reverse,
+    /// Either way, picked at random for each question.
+    Mixed,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Forward => "forward",
+            Direction::Reverse => "reverse",
+            Direction::Mixed => "mixed",
+        }
+    }
+}
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "forward" => Ok(Direction::Forward),
+            "reverse" => Ok(Direction::Reverse),
+            "mixed" => Ok(Direction::Mixed),
+            other => Err(format!(
+                "unknown direction '{other}' (expected forward, reverse or mixed)"
+            )),
+        }
+    }
+}
+impl ToSql for Direction {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+impl FromSql for Direction {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|_| FromSqlError::InvalidType)
+    }
 }
 
+/// Where the image on a side of a card comes from.
 #[derive(Clone, Debug, PartialEq)]
-pub struct OptionPair(pub Option<String>, pub Option<PathBuf>);
+pub enum Image {
+    /// An image file, already resolved against the media directory.
+    File(PathBuf),
+    /// The hash of a [`Media`](crate::db::Media) stored in the database.
+    Media(String),
+}
+impl std::fmt::Display for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Image::File(path) => write!(f, "{}", path.to_str().unwrap_or_default()),
+            Image::Media(hash) => write!(f, "media:{}", hash),
+        }
+    }
+}
+
+/// One side of a card: its text and its image, `None` where unused.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OptionPair(pub Option<String>, pub Option<Image>);
 impl std::fmt::Display for OptionPair {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -69,14 +157,22 @@ impl std::fmt::Display for OptionPair {
                 (true, true) => ", ",
                 _ => "",
             },
-            self.1
-                .as_ref()
-                .map_or("", |path| path.to_str().unwrap_or_default())
+            self.1.as_ref().map_or(String::new(), Image::to_string)
         )
     }
 }
-impl From<(Option<String>, Option<PathBuf>)> for OptionPair {
-    fn from(value: (Option<String>, Option<PathBuf>)) -> Self {
+impl OptionPair {
+    /// Same image, and text that only differs in script, width or romaji.
+    pub fn equivalent(&self, other: &OptionPair) -> bool {
+        self.1 == other.1
+            && match (&self.0, &other.0) {
+                (Some(a), Some(b)) => normalize::equivalent(a, b),
+                (a, b) => a == b,
+            }
+    }
+}
+impl From<(Option<String>, Option<Image>)> for OptionPair {
+    fn from(value: (Option<String>, Option<Image>)) -> Self {
         Self(value.0, value.1)
     }
 }
@@ -98,6 +194,12 @@ impl Question {
         vec
     }
 
+    /// Whether choosing `option` answers the question correctly.
+    pub fn is_correct(&self, option: &OptionPair) -> bool {
+        option.equivalent(&self.correct_option)
+    }
+
+    /// Every option in random order, along with the index of the correct one.
     pub fn get_options_randomize(&self) -> (Vec<OptionPair>, usize) {
         let mut opts = self.get_all_options_tuple();
         let correct = &self.correct_option;
@@ -105,43 +207,137 @@ impl Question {
         let index = opts.iter().position(|r| r == correct).unwrap();
         (opts, index)
     }
-    fn set_score(&mut self, conn: &Connection, score: i32) -> Result<i32> {
-        match Card::change_score(conn, self.card_id, score) {
-            Ok(score) => {
-                self.score = score;
-                Ok(score)
-            }
-            Err(err) => Err(err),
-        }
+    /// Lets the card's scheduler grade the answer, saves the profile's new progress and
+    /// records the answer in the card's review history.
+    /// Returns the score before and after answering.
+    pub fn record_answer(
+        &mut self,
+        conn: &Connection,
+        answer: Answer,
+        chosen_option: Option<&OptionPair>,
+        response_time: Duration,
+    ) -> Result<(i32, i32)> {
+        let now = now_timestamp();
+        let before = Card::get_progress(conn, &self.profile_name, self.card_id)?;
+        let after = self.scheduler.review(before, answer, response_time, now);
+        Card::set_progress(conn, &self.profile_name, self.card_id, &after)?;
+        self.score = after.score;
+        let (score_before, score_after) = (before.score, after.score);
+        Review::add(
+            conn,
+            Review {
+                id: None,
+                card_id: self.card_id,
+                profile_name: self.profile_name.clone(),
+                timestamp: now,
+                chosen_option: chosen_option.map(OptionPair::to_string),
+                answer,
+                response_time,
+                score_before,
+                score_after,
+                session_id: self.session_id,
+            },
+        )?;
+        Ok((score_before, score_after))
     }
-    pub fn get_score(&self, conn: &Connection) -> Result<i32> {
-        Ok(Card::get_score(conn, self.card_id)?.unwrap_or(0))
+}
+
+/// How a quiz went, for showing once it is over.
+#[derive(Debug, Clone)]
+pub struct SessionSummary {
+    pub session: Session,
+    /// Cards answered incorrectly or not known.
+    pub missed: Vec<Card>,
+}
+impl SessionSummary {
+    pub fn answered(&self) -> u32 {
+        self.session.correct + self.session.incorrect + self.session.dont_know
     }
-    pub fn increment_score(&mut self, conn: &Connection) -> Result<i32> {
-        self.set_score(conn, self.get_score(conn)? + 1)
+    /// Share of answered questions that were correct, between 0 and 1.
+    pub fn accuracy(&self) -> f64 {
+        match self.answered() {
+            0 => 0.0,
+            answered => self.session.correct as f64 / answered as f64,
+        }
+    }
+}
+
+/// Marks the session as over and gathers its results.
+pub fn finish_session(conn: &Connection, session_id: i64) -> Result<SessionSummary> {
+    let session = Session::finish(conn, session_id)?;
+    let missed = Session::get_missed_cards(conn, session_id)?;
+    debug!(
+        "[Session] Session {} ended with {}/{} correct.",
+        session_id, session.correct, session.question_count
+    );
+    Ok(SessionSummary { session, missed })
+}
+
+/// How the cards of a quiz are picked.
+#[derive(Debug, Clone)]
+pub enum Selection {
+    /// Whole pools at random from one category and the categories nested in it.
+    RandomPools(Category),
+    /// Cards due today by their schedule, optionally only from one category and the categories
+    /// nested in it.
+    DueToday(Option<Category>),
+}
+impl Selection {
+    pub fn mode(&self) -> SessionMode {
+        match self {
+            Selection::RandomPools(_) => SessionMode::RandomPools,
+            Selection::DueToday(_) => SessionMode::DueToday,
+        }
     }
-    pub fn decrement_score(&mut self, conn: &Connection) -> Result<i32> {
-        self.set_score(conn, self.get_score(conn)? - 1)
+    pub fn category(&self) -> Option<&Category> {
+        match self {
+            Selection::RandomPools(category) => Some(category),
+            Selection::DueToday(category) => category.as_ref(),
+        }
     }
 }
 
-pub(crate) fn get_question_cards(
+/// Picks up to `question_count` cards to ask `profile_name` about. Fails if a category
+/// to pick pools from has no pools, or no cards matching `tags`.
+pub fn get_question_cards(
     conn: &Connection,
+    profile_name: &str,
     question_count: u32,
-    category: Category,
-) -> Vec<Card> {
+    selection: Selection,
+    tags: &TagFilter,
+) -> Result<Vec<Card>> {
     debug!("[Setup] Obtaining {} questions.", question_count);
+    let category = match selection {
+        Selection::RandomPools(category) => category,
+        Selection::DueToday(category) => {
+            let category_name = category.as_ref().map(|c| c.name.as_str());
+            let cards =
+                Card::get_due_today(conn, profile_name, category_name, tags, question_count)?;
+            debug!("[Setup] {} cards are due today.", cards.len());
+            return Ok(cards);
+        }
+    };
     let questions_usize = question_count as usize;
     let mut cards = Vec::with_capacity(questions_usize);
+    let pool_count = Pool::get_all_in_category_tree(conn, &category.name)?.len();
+    let mut empty_pools = HashSet::new();
     while cards.len() < questions_usize {
-        let pool = rand_pool(conn, &category).unwrap_or_else(|| {
-            warn!("[Setup] No pools found.");
-            exit(0)
-        });
+        if pool_count > 0 && empty_pools.len() == pool_count {
+            warn!("[Setup] No cards left that match the tag filter.");
+            break;
+        }
+        let pool = rand_pool(conn, &category)?;
         debug!("[Setup] Picked pool {:?}", pool);
-        let mut pool_cards = Card::get_in_pool(conn, pool.id).unwrap();
+        let mut pool_cards = Card::get_in_pool_filtered(conn, pool.id, tags)?;
         pool_cards.shuffle(&mut rng());
-        debug!("[Setup] ...which contains {} cards.", pool_cards.len());
+        debug!(
+            "[Setup] ...which contains {} matching cards.",
+            pool_cards.len()
+        );
+        if pool_cards.is_empty() {
+            empty_pools.insert(pool.id);
+            continue;
+        }
 
         if cards.len() + pool_cards.len() > questions_usize {
             let to_keep = questions_usize - cards.len();
@@ -156,18 +352,34 @@ pub(crate) fn get_question_cards(
         }
     }
 
-    cards
+    if cards.is_empty() {
+        return Err(Error::EmptyCategory(category.name));
+    }
+    Ok(cards)
 }
 
-pub(crate) fn init_questions(
+/// Turns `cards` into questions with up to `choices_count` options each, using the scheduler
+/// and direction of each card's category unless `scheduler_config` or `direction` override
+/// them. Relative image paths are resolved against `media_dir`. Cards with a missing image or
+/// without any other card to use as an option are skipped, and only if that leaves no
+/// questions is it an error.
+pub fn init_questions(
     conn: &Connection,
+    profile_name: &str,
     cards: Vec<Card>,
     choices_count: u32,
+    scheduler_config: &SchedulerConfig,
+    direction: Option<Direction>,
+    media_dir: &Path,
 ) -> Result<Vec<Question>> {
     let now = Instant::now();
     let mut questions: Vec<Question> = Vec::with_capacity(cards.len());
+    let mut settings: HashMap<Option<String>, (Arc<dyn Scheduler>, Direction)> = HashMap::new();
     let mut cached_pool_id: Option<i32> = None;
-    let mut cached_pool_cards: Option<Vec<Card>> = None;
+    let mut cached_pool_cards: Vec<Card> = vec![];
+    let mut cached_generated: HashMap<i32, (i32, String)> = HashMap::new();
+    // Skipped cards are only an error if none are left.
+    let mut skipped: Option<Error> = None;
     for card in cards {
         let card_id =
             extract_or_continue!(card.id, "[Setup] Card does not have an `id`! Skipping...");
@@ -176,31 +388,120 @@ pub(crate) fn init_questions(
             "[Setup] Card does not have a `pool_id`! Skipping..."
         );
 
-        match cached_pool_id {
-            None => cached_pool_cards = fetch_pool_cards_and_cache!(&conn, pool_id, cached_pool_id),
-            Some(cached) => {
-                if cached != pool_id {
-                    cached_pool_cards = fetch_pool_cards_and_cache!(&conn, pool_id, cached_pool_id);
-                }
-            }
+        if cached_pool_id != Some(pool_id) {
+            cached_pool_cards = Card::get_in_pool(conn, pool_id)?;
+            cached_generated = Note::generated_in_pool(conn, pool_id)?;
+            cached_pool_id = Some(pool_id);
+        }
+        if let Err(err) = check_images(&card, media_dir) {
+            warn!("[Setup] Skipping Card {}: {}", card_id, err);
+            skipped = Some(err);
+            continue;
         }
-        // cached_pool_cards should be Some() by now
-
-        let mut cards = cached_pool_cards.clone().unwrap();
-        cards.retain(|c| c.id.unwrap() != card_id);
 
-        let mut backside: Vec<OptionPair> = cards
-            .iter()
-            .map(|c| {
-                let back_text = c.back.clone();
-                let back_img = c.back_image.clone();
-                card_face_tuple!(back_text, back_img)
+        let (scheduler, direction) = settings
+            .entry(card.category_name.clone())
+            .or_insert_with(|| {
+                let category = card
+                    .category_name
+                    .as_deref()
+                    .and_then(|name| Category::get_one(conn, name).ok());
+                let kind = scheduler_config.resolve(category.as_ref().and_then(|c| c.scheduler));
+                let direction = direction
+                    .or(category.and_then(|category| category.direction))
+                    .unwrap_or_default();
+                debug!(
+                    "[Setup] Using {} scheduler and {} direction for Category {:?}",
+                    kind, direction, card.category_name
+                );
+                (scheduler_config.build(kind), direction)
             })
-            .collect();
-        backside.shuffle(&mut rng());
+            .clone();
+        let reversed = match direction {
+            Direction::Forward => false,
+            Direction::Reverse => true,
+            Direction::Mixed => rng().random_bool(0.5),
+        };
+        // The side that is asked for, of the card or of another one in the pool.
+        let answer_side = |c: &Card| {
+            let (front, back) = faces(c, media_dir);
+            if reversed {
+                front
+            } else {
+                back
+            }
+        };
 
-        let incorrect_choices_count = (choices_count - 1) as usize;
+        let correct_option = answer_side(&card);
+        // Another way of writing the answer would be a second correct option, and a card with
+        // a missing image could not be shown.
+        let to_options = |cards: Vec<&Card>| {
+            let mut options: Vec<OptionPair> = cards
+                .into_iter()
+                .filter(|c| match check_images(c, media_dir) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        warn!("[Setup] Not using Card {:?} as an option: {}", c.id, err);
+                        false
+                    }
+                })
+                .map(answer_side)
+                .collect();
+            options.retain(|option| !option.equivalent(&correct_option));
+            options.shuffle(&mut rng());
+            options
+        };
+        let incorrect_choices_count = choices_count.saturating_sub(1) as usize;
 
+        let others = cached_pool_cards.iter().filter(|c| c.id != Some(card_id));
+        let plain: Vec<&Card> = others
+            .clone()
+            .filter(|c| c.id.is_some_and(|id| !cached_generated.contains_key(&id)))
+            .collect();
+        let backside = match cached_generated.get(&card_id) {
+            None => to_options(plain),
+            // Cards generated from notes get options from the same template of other notes
+            // first, so that a reading is not offered as a meaning, and from plain cards last.
+            Some((note_id, template)) => {
+                let mut backside = to_options(
+                    others
+                        .filter(|c| {
+                            c.id.and_then(|id| cached_generated.get(&id)).is_some_and(
+                                |(other_note_id, other_template)| {
+                                    other_template == template && other_note_id != note_id
+                                },
+                            )
+                        })
+                        .collect(),
+                );
+                if backside.len() < incorrect_choices_count {
+                    let elsewhere = Note::template_siblings(conn, *note_id, template)?;
+                    backside.extend(to_options(
+                        elsewhere
+                            .iter()
+                            .filter(|c| c.pool_id != Some(pool_id))
+                            .collect(),
+                    ));
+                }
+                if backside.len() < incorrect_choices_count {
+                    backside.extend(to_options(plain));
+                }
+                backside
+            }
+        };
+
+        if backside.is_empty() && incorrect_choices_count > 0 {
+            warn!(
+                "[Setup] Pool {} has no other cards to use as options for Card {}! Skipping...",
+                pool_id, card_id
+            );
+            skipped = Some(Error::PoolTooSmall {
+                pool_id,
+                cards: cached_pool_cards.len(),
+                needed: choices_count,
+            });
+            continue;
+        }
         let incorrect_options = if backside.len() < incorrect_choices_count {
             warn!("[Setup] Cardlist in Pool {} does not have enough cards to populate the incorrect options! \
             User Requested {} (incorrect) choices, only have {} choices left in pool!", pool_id,
@@ -210,12 +511,32 @@ pub(crate) fn init_questions(
             backside[..incorrect_choices_count].to_vec()
         };
 
+        let (front, back) = faces(&card, media_dir);
+        let (front_audio, back_audio) = (
+            audio_path(&card.front_audio, media_dir),
+            audio_path(&card.back_audio, media_dir),
+        );
+        let (front, front_audio, back_audio) = if reversed {
+            (back, back_audio, front_audio)
+        } else {
+            (front, front_audio, back_audio)
+        };
+
         questions.push(Question {
             card_id,
-            score: card.score.unwrap_or(0),
-            front: card_face_tuple!(card.front, card.front_image),
-            correct_option: card_face_tuple!(card.back, card.back_image),
+            profile_name: profile_name.to_string(),
+            session_id: None,
+            score: Card::get_progress(conn, profile_name, card_id)?.score,
+            scheduler,
+            front,
+            correct_option,
             incorrect_options,
+            reversed,
+            front_audio,
+            back_audio,
+            reading: non_empty(card.reading),
+            notes: non_empty(card.notes),
+            example: non_empty(card.example),
         })
     }
 
@@ -223,22 +544,91 @@ pub(crate) fn init_questions(
         "[Setup] Initialized questions in {} ms.",
         now.elapsed().as_millis()
     );
-    Ok(questions)
+    match skipped {
+        Some(err) if questions.is_empty() => Err(err),
+        _ => Ok(questions),
+    }
 }
 
-pub fn category(conn: &Connection, selected: Option<&str>) -> Option<Category> {
+/// The category named `selected`, or a random one. Categories in the trash are left out.
+pub fn category(conn: &Connection, selected: Option<&str>) -> Result<Category> {
     match selected {
-        Some(choice) => Category::get_one(conn, choice).ok(),
+        Some(choice) => Category::get_all(conn)?
+            .into_iter()
+            .find(|category| category.name == choice)
+            .ok_or_else(|| Error::UnknownCategory(choice.to_string())),
         None => rand_category(conn),
     }
 }
 
-fn rand_category(conn: &Connection) -> Option<Category> {
-    let categories = Category::get_all(conn).unwrap();
-    categories.choose(&mut rng()).cloned()
+fn rand_category(conn: &Connection) -> Result<Category> {
+    let categories = Category::get_all(conn)?;
+    categories
+        .choose(&mut rng())
+        .cloned()
+        .ok_or(Error::NoCategories)
 }
 
-pub(crate) fn rand_pool(conn: &Connection, category: &Category) -> Option<Pool> {
-    let pools = Pool::get_all_in_category(conn, &category.name).unwrap();
-    pools.choose(&mut rng()).cloned()
+/// Picks a pool from the category or any category nested in it.
+pub fn rand_pool(conn: &Connection, category: &Category) -> Result<Pool> {
+    let pools = Pool::get_all_in_category_tree(conn, &category.name)?;
+    pools
+        .choose(&mut rng())
+        .cloned()
+        .ok_or_else(|| Error::NoPools(category.name.clone()))
+}
+
+/// The front and back of `card` as they are shown.
+fn faces(card: &Card, media_dir: &Path) -> (OptionPair, OptionPair) {
+    (
+        card_face_tuple!(
+            card.front.clone(),
+            card.front_image.clone(),
+            card.front_media.clone(),
+            media_dir
+        ),
+        card_face_tuple!(
+            card.back.clone(),
+            card.back_image.clone(),
+            card.back_media.clone(),
+            media_dir
+        ),
+    )
+}
+
+fn non_empty(text: String) -> Option<String> {
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+fn audio_path(path: &Path, media_dir: &Path) -> Option<PathBuf> {
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(db::resolve_path(media_dir, path))
+    }
+}
+
+/// Fails if an image of `card` is a file that is not in `media_dir`, as the quiz could not
+/// show it.
+fn check_images(card: &Card, media_dir: &Path) -> Result<()> {
+    match [
+        (&card.front_image, &card.front_media),
+        (&card.back_image, &card.back_media),
+    ]
+    .into_iter()
+    .find(|(path, media)| {
+        media.is_none()
+            && !path.as_os_str().is_empty()
+            && !db::resolve_path(media_dir, path).exists()
+    }) {
+        Some((path, _)) => Err(Error::MissingImage {
+            card_id: card.id.unwrap_or_default(),
+            path: db::resolve_path(media_dir, path),
+        }),
+        None => Ok(()),
+    }
 }
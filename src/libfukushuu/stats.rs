@@ -0,0 +1,307 @@
+use log::debug;
+use rusqlite::{named_params, Connection, Result};
+use serde::Serialize;
+use std::time::Instant;
+
+/// How well a profile knows the cards of a category or pool.
+#[derive(Debug, Clone, Serialize)]
+pub struct Accuracy {
+    /// Category name, or pool id for pools.
+    pub name: String,
+    pub answered: u32,
+    pub correct: u32,
+    /// Share of answers that were correct, between 0 and 1.
+    pub accuracy: f64,
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct CardStat {
+    pub card_id: i32,
+    pub front: String,
+    pub back: String,
+    pub category_name: Option<String>,
+    pub score: i32,
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyCount {
+    /// Local date as `YYYY-MM-DD`.
+    pub date: String,
+    pub answered: u32,
+    pub correct: u32,
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub profile_name: String,
+    pub categories: Vec<Accuracy>,
+    pub pools: Vec<Accuracy>,
+    /// Answered cards with the lowest scores, lowest first.
+    pub weakest_cards: Vec<CardStat>,
+    pub never_answered_count: u32,
+    /// Some of the cards that were never answered.
+    pub never_answered: Vec<CardStat>,
+    /// Answers per day, oldest first. Days without answers are left out.
+    pub daily: Vec<DailyCount>,
+    /// Days in a row with answers, up to today (or yesterday, if nothing was answered yet today).
+    pub current_streak: u32,
+    pub longest_streak: u32,
+}
+
+/// Everything there is to know about a profile's progress. `limit` caps the card lists.
+pub fn compute(connection: &Connection, profile_name: &str, limit: u32) -> super::Result<Stats> {
+    let now = Instant::now();
+    let days = answer_days(connection, profile_name)?;
+    let today = today(connection)?;
+    let (current_streak, longest_streak) = streaks(&days, today);
+    let stats = Stats {
+        profile_name: profile_name.to_string(),
+        categories: category_accuracy(connection, profile_name)?,
+        pools: pool_accuracy(connection, profile_name)?,
+        weakest_cards: weakest_cards(connection, profile_name, limit)?,
+        never_answered_count: never_answered_count(connection, profile_name)?,
+        never_answered: never_answered(connection, profile_name, limit)?,
+        daily: daily_counts(connection, profile_name)?,
+        current_streak,
+        longest_streak,
+    };
+    debug!(
+        "[Stats] Computed stats in {} ms.",
+        now.elapsed().as_millis()
+    );
+
+    Ok(stats)
+}
+
+fn accuracy_from_row(row: &rusqlite::Row) -> Result<Accuracy> {
+    let answered: u32 = row.get(1)?;
+    let correct: u32 = row.get(2)?;
+    Ok(Accuracy {
+        name: row.get(0)?,
+        answered,
+        correct,
+        accuracy: if answered == 0 {
+            0.0
+        } else {
+            correct as f64 / answered as f64
+        },
+    })
+}
+
+fn category_accuracy(connection: &Connection, profile_name: &str) -> Result<Vec<Accuracy>> {
+    let mut statement = connection.prepare(
+        "SELECT Card.categoryName, COUNT(*), SUM(Review.answer = 'correct') \
+        FROM Review JOIN Card ON Card.id = Review.cardId \
+        WHERE Review.profileName = :profileName AND Card.categoryName IS NOT NULL \
+        AND Card.trashId IS NULL \
+        GROUP BY Card.categoryName ORDER BY Card.categoryName",
+    )?;
+    let rows = statement.query_map(&[(":profileName", &profile_name)], accuracy_from_row)?;
+
+    rows.collect()
+}
+
+fn pool_accuracy(connection: &Connection, profile_name: &str) -> Result<Vec<Accuracy>> {
+    let mut statement = connection.prepare(
+        "SELECT CAST(Card.poolId AS TEXT), COUNT(*), SUM(Review.answer = 'correct') \
+        FROM Review JOIN Card ON Card.id = Review.cardId \
+        WHERE Review.profileName = :profileName AND Card.poolId IS NOT NULL \
+        AND Card.trashId IS NULL \
+        GROUP BY Card.poolId ORDER BY Card.poolId",
+    )?;
+    let rows = statement.query_map(&[(":profileName", &profile_name)], accuracy_from_row)?;
+
+    rows.collect()
+}
+
+fn card_stat_from_row(row: &rusqlite::Row) -> Result<CardStat> {
+    Ok(CardStat {
+        card_id: row.get(0)?,
+        front: row.get(1)?,
+        back: row.get(2)?,
+        category_name: row.get(3)?,
+        score: row.get(4)?,
+    })
+}
+
+fn weakest_cards(connection: &Connection, profile_name: &str, limit: u32) -> Result<Vec<CardStat>> {
+    let mut statement = connection.prepare(
+        "SELECT Card.id, Card.front, Card.back, Card.categoryName, CardProgress.score \
+        FROM CardProgress JOIN Card ON Card.id = CardProgress.cardId \
+        WHERE CardProgress.profileName = :profileName AND Card.trashId IS NULL \
+        ORDER BY CardProgress.score, Card.id LIMIT :limit",
+    )?;
+    let rows = statement.query_map(
+        named_params! { ":profileName": profile_name, ":limit": limit },
+        card_stat_from_row,
+    )?;
+
+    rows.collect()
+}
+
+const NEVER_ANSWERED_SQL: &str = "FROM Card WHERE trashId IS NULL AND NOT EXISTS \
+    (SELECT 1 FROM Review WHERE Review.cardId = Card.id AND Review.profileName = :profileName)";
+
+fn never_answered_count(connection: &Connection, profile_name: &str) -> Result<u32> {
+    connection.query_row(
+        &format!("SELECT COUNT(*) {}", NEVER_ANSWERED_SQL),
+        &[(":profileName", &profile_name)],
+        |row| row.get(0),
+    )
+}
+
+fn never_answered(
+    connection: &Connection,
+    profile_name: &str,
+    limit: u32,
+) -> Result<Vec<CardStat>> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT id, front, back, categoryName, 0 {} ORDER BY id LIMIT :limit",
+        NEVER_ANSWERED_SQL
+    ))?;
+    let rows = statement.query_map(
+        named_params! { ":profileName": profile_name, ":limit": limit },
+        card_stat_from_row,
+    )?;
+
+    rows.collect()
+}
+
+fn daily_counts(connection: &Connection, profile_name: &str) -> Result<Vec<DailyCount>> {
+    let mut statement = connection.prepare(
+        "SELECT date(timestamp, 'unixepoch', 'localtime') AS day, COUNT(*), SUM(answer = 'correct') \
+        FROM Review WHERE profileName = :profileName GROUP BY day ORDER BY day",
+    )?;
+    let rows = statement.query_map(&[(":profileName", &profile_name)], |row| {
+        Ok(DailyCount {
+            date: row.get(0)?,
+            answered: row.get(1)?,
+            correct: row.get(2)?,
+        })
+    })?;
+
+    rows.collect()
+}
+
+/// Local days with answers as Julian day numbers, oldest first.
+fn answer_days(connection: &Connection, profile_name: &str) -> Result<Vec<i64>> {
+    let mut statement = connection.prepare(
+        "SELECT DISTINCT CAST(julianday(date(timestamp, 'unixepoch', 'localtime')) AS INTEGER) AS day \
+        FROM Review WHERE profileName = :profileName ORDER BY day",
+    )?;
+    let rows = statement.query_map(&[(":profileName", &profile_name)], |row| row.get(0))?;
+
+    rows.collect()
+}
+
+fn today(connection: &Connection) -> Result<i64> {
+    connection.query_row(
+        "SELECT CAST(julianday(date('now', 'localtime')) AS INTEGER)",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// The current and longest runs of consecutive `days`, which must be sorted and distinct.
+fn streaks(days: &[i64], today: i64) -> (u32, u32) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous = None;
+    for &day in days {
+        run = match previous {
+            Some(previous) if day == previous + 1 => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(day);
+    }
+    let current = match previous {
+        Some(last) if last >= today - 1 => run,
+        _ => 0,
+    };
+    (current, longest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{now_timestamp, Answer, Card, Review, Schedule};
+    use crate::scheduler::Progress;
+    use crate::test_support::{setup, PROFILE};
+    use std::time::Duration;
+
+    /// Records `answer` for the card with `front`, leaving it at `score`.
+    fn answer(conn: &Connection, front: &str, answer: Answer, score: i32) -> i32 {
+        let card_id = Card::get_all(conn)
+            .unwrap()
+            .into_iter()
+            .find(|card| card.front == front)
+            .and_then(|card| card.id)
+            .unwrap();
+        Review::add(
+            conn,
+            Review {
+                id: None,
+                card_id,
+                profile_name: PROFILE.to_string(),
+                timestamp: now_timestamp(),
+                chosen_option: None,
+                answer,
+                response_time: Duration::from_secs(1),
+                score_before: 0,
+                score_after: score,
+                session_id: None,
+            },
+        )
+        .unwrap();
+        let progress = Progress {
+            score,
+            schedule: Schedule::default(),
+        };
+        Card::set_progress(conn, PROFILE, card_id, &progress).unwrap();
+        card_id
+    }
+
+    #[test]
+    fn accuracy_and_weakest_cards_leave_out_the_trash() {
+        let conn = setup();
+        answer(&conn, "犬", Answer::Correct, 2);
+        answer(&conn, "猫", Answer::Incorrect, -1);
+        answer(&conn, "一人", Answer::DontKnow, -2);
+        let trashed = answer(&conn, "魚", Answer::Incorrect, -5);
+        Card::trash(&conn, trashed).unwrap();
+
+        let stats = compute(&conn, PROFILE, 10).unwrap();
+        let accuracies = |accuracies: &[Accuracy]| {
+            accuracies
+                .iter()
+                .map(|a| (a.name.clone(), a.answered, a.correct, a.accuracy))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            accuracies(&stats.categories),
+            [
+                ("Animals".to_string(), 2, 1, 0.5),
+                ("Lonely".to_string(), 1, 0, 0.0)
+            ]
+        );
+        assert_eq!(
+            accuracies(&stats.pools),
+            [("1".to_string(), 2, 1, 0.5), ("2".to_string(), 1, 0, 0.0)]
+        );
+        let weakest: Vec<_> = stats
+            .weakest_cards
+            .iter()
+            .map(|card| (card.front.as_str(), card.score))
+            .collect();
+        assert_eq!(weakest, [("一人", -2), ("猫", -1), ("犬", 2)]);
+        assert_eq!(stats.never_answered_count, 1);
+        assert_eq!(stats.never_answered[0].front, "鳥");
+        assert_eq!((stats.current_streak, stats.longest_streak), (1, 1));
+    }
+
+    #[test]
+    fn streaks_count_consecutive_days() {
+        assert_eq!(streaks(&[], 100), (0, 0));
+        assert_eq!(streaks(&[90, 91, 92, 98, 99], 100), (2, 3));
+        assert_eq!(streaks(&[90, 91, 92, 98, 100], 100), (1, 3));
+        assert_eq!(streaks(&[90, 91, 92], 100), (0, 3));
+    }
+}
@@ -0,0 +1,170 @@
+use super::db::{Answer, Media, TagFilter};
+use super::scheduler::SchedulerConfig;
+use super::shitsumon::{self, Direction, OptionPair, Question, Selection, SessionSummary};
+use super::stats::{self, Stats};
+use super::Result;
+use log::{debug, warn};
+use rusqlite::Connection;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Work for the [`Worker`] to do on its connection.
+#[derive(Debug)]
+pub enum Request {
+    /// [`Question::record_answer`] on a copy of the question.
+    RecordAnswer {
+        question: Question,
+        answer: Answer,
+        chosen_option: Option<OptionPair>,
+        response_time: Duration,
+    },
+    /// Picks cards with [`shitsumon::get_question_cards`] and turns them into questions.
+    LoadQuestions {
+        profile_name: String,
+        question_count: u32,
+        choices_count: u32,
+        selection: Selection,
+        tags: TagFilter,
+        scheduler_config: SchedulerConfig,
+        direction: Option<Direction>,
+        media_dir: PathBuf,
+    },
+    /// [`shitsumon::finish_session`].
+    FinishSession(i64),
+    /// [`stats::compute`].
+    Stats { profile_name: String, limit: u32 },
+    /// The bytes of a [`Media`], by hash.
+    LoadMedia(String),
+}
+
+/// The result of a [`Request`], sent back in the order the requests were made.
+#[derive(Debug)]
+pub enum Response {
+    /// The score before and after answering the card.
+    AnswerRecorded {
+        card_id: i32,
+        result: Result<(i32, i32)>,
+    },
+    QuestionsLoaded(Result<Vec<Question>>),
+    SessionFinished(Result<SessionSummary>),
+    Stats(Result<Stats>),
+    MediaLoaded {
+        hash: String,
+        result: Result<Vec<u8>>,
+    },
+}
+
+/// Owns a connection on a thread of its own, so that callers which cannot wait on the
+/// database (like the GUI's frame loop) can send it [`Request`]s and pick up the
+/// [`Response`]s later.
+pub struct Worker {
+    requests: Sender<Request>,
+    responses: Receiver<Response>,
+    handle: JoinHandle<Connection>,
+}
+
+impl Worker {
+    /// Moves `connection` to a new thread. `notify` is called on that thread after every
+    /// response, e.g. to wake up a UI waiting for one.
+    pub fn spawn(connection: Connection, notify: impl Fn() + Send + 'static) -> Worker {
+        let (requests, request_rx) = mpsc::channel();
+        let (response_tx, responses) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            debug!("[Worker] Started");
+            for request in request_rx {
+                let response = serve(&connection, request);
+                // Answers still have to be saved when nobody is listening anymore.
+                if response_tx.send(response).is_ok() {
+                    notify();
+                }
+            }
+            debug!("[Worker] Stopped");
+            connection
+        });
+
+        Worker {
+            requests,
+            responses,
+            handle,
+        }
+    }
+
+    /// Queues `request`. Returns `false` if the worker is gone.
+    pub fn send(&self, request: Request) -> bool {
+        self.requests.send(request).is_ok()
+    }
+
+    /// The next response if one is ready, without waiting.
+    pub fn try_recv(&self) -> Option<Response> {
+        match self.responses.try_recv() {
+            Ok(response) => Some(response),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                warn!("[Worker] Worker is gone");
+                None
+            }
+        }
+    }
+
+    /// Waits for the next response. `None` if the worker is gone.
+    pub fn recv(&self) -> Option<Response> {
+        self.responses.recv().ok()
+    }
+
+    /// Finishes every queued request, then stops the thread and hands back its connection.
+    /// `None` if the thread panicked.
+    pub fn shutdown(self) -> Option<Connection> {
+        drop(self.requests);
+        self.handle.join().ok()
+    }
+}
+
+fn serve(conn: &Connection, request: Request) -> Response {
+    match request {
+        Request::RecordAnswer {
+            mut question,
+            answer,
+            chosen_option,
+            response_time,
+        } => Response::AnswerRecorded {
+            card_id: question.card_id,
+            result: question.record_answer(conn, answer, chosen_option.as_ref(), response_time),
+        },
+        Request::LoadQuestions {
+            profile_name,
+            question_count,
+            choices_count,
+            selection,
+            tags,
+            scheduler_config,
+            direction,
+            media_dir,
+        } => Response::QuestionsLoaded(
+            shitsumon::get_question_cards(conn, &profile_name, question_count, selection, &tags)
+                .and_then(|cards| {
+                    shitsumon::init_questions(
+                        conn,
+                        &profile_name,
+                        cards,
+                        choices_count,
+                        &scheduler_config,
+                        direction,
+                        &media_dir,
+                    )
+                }),
+        ),
+        Request::FinishSession(session_id) => {
+            Response::SessionFinished(shitsumon::finish_session(conn, session_id))
+        }
+        Request::Stats {
+            profile_name,
+            limit,
+        } => Response::Stats(stats::compute(conn, &profile_name, limit)),
+        Request::LoadMedia(hash) => Response::MediaLoaded {
+            result: Media::get(conn, &hash).map(|media| media.data),
+            hash,
+        },
+    }
+}
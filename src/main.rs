@@ -1,10 +1,8 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use colored::Colorize;
 use env_logger::Env;
-use libfukushuu::shitsumon::category;
 use log::{debug, warn};
 use rusqlite::{Connection, Result};
-use std::cmp::PartialEq;
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -13,15 +11,22 @@ mod cli;
 #[cfg(feature = "gui")]
 mod gui;
 
-mod libfukushuu;
-
-use crate::libfukushuu::db;
-use crate::libfukushuu::shitsumon::{get_question_cards, init_questions};
+use libfukushuu::audio::{self, AudioPlayer};
+use libfukushuu::db;
+use libfukushuu::db::{Profile, Session, TagFilter};
+use libfukushuu::scheduler::{SchedulerConfig, SchedulerKind};
+use libfukushuu::shitsumon::{
+    category, get_question_cards, init_questions, Direction, Question, Selection,
+};
+use libfukushuu::stats::{self, Stats};
 
+#[cfg(feature = "cli")]
 #[derive(Debug, PartialEq)]
 enum Choice {
     Option(usize),
     DontKnow,
+    /// Play the sound of the question again.
+    Replay,
     Quit,
 }
 
@@ -31,20 +36,72 @@ enum Choice {
 struct Args {
     #[arg(short, long, value_name = "FILE", default_value = "flashcards.db")]
     db: Option<PathBuf>,
+    /// Whose progress to quiz on and save to. Created for quizzing if it does not exist yet.
+    #[arg(short, long, default_value = db::DEFAULT_PROFILE)]
+    profile: String,
+    /// Directory that relative image and sound paths are found in. Defaults to the one the database is in.
+    #[arg(long, value_name = "DIR")]
+    media_dir: Option<PathBuf>,
+    /// Category to quiz on, including every category nested in it. Picked at random if not given.
     #[arg(long)]
     category: Option<String>,
+    /// Quiz on cards that are due today instead of random pools.
+    /// Uses all categories unless `--category` is given.
+    #[arg(long)]
+    due: bool,
+    /// Only ask cards with this tag. Can be given more than once to allow any of several tags.
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Never ask cards with this tag. Can be given more than once.
+    #[arg(long = "exclude-tag", value_name = "TAG")]
+    exclude_tags: Vec<String>,
+    /// Scheduler for every card, overriding the one set on its category.
+    /// One of `score`, `sm2` or `fsrs`.
+    #[arg(long)]
+    scheduler: Option<SchedulerKind>,
+    /// Which side to ask for, overriding the one set on the category.
+    /// One of `forward` (front to back), `reverse` (back to front) or `mixed`.
+    #[arg(long)]
+    direction: Option<Direction>,
+    /// FSRS: probability of still remembering a card when it comes due, between 0 and 1.
+    #[arg(long, default_value = "0.9")]
+    desired_retention: f64,
+    /// FSRS: 17 comma-separated weights to use instead of the defaults.
+    #[arg(long, value_delimiter = ',')]
+    fsrs_weights: Option<Vec<f64>>,
     #[arg(short, long, default_value = "20")]
     question_count: u32,
     #[arg(short, long, default_value = "4")]
     choices_count: u32,
     #[arg(short, long, default_value = "error")]
     log_level: String,
+    /// Quiz in the terminal instead of a window.
+    #[cfg(all(feature = "cli", feature = "gui"))]
+    #[arg(long)]
+    cli: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show accuracy, weak cards and answer streaks for the profile instead of quizzing.
+    Stats {
+        /// Print the statistics as JSON.
+        #[arg(long)]
+        json: bool,
+        /// How many cards to list as weakest and never answered.
+        #[arg(long, default_value = "10")]
+        limit: u32,
+    },
 }
 
+#[cfg(feature = "cli")]
 impl Choice {
     fn from_str(choices_count: u32, input: &str) -> Choice {
         match input {
             "q" => Choice::Quit,
+            "r" => Choice::Replay,
             input => match input.parse::<usize>() {
                 Ok(num) => {
                     if num > choices_count as usize {
@@ -66,11 +123,17 @@ impl Choice {
 
 #[derive(Debug, Error)]
 enum Error {
-    #[error("no categories!")]
-    NoCategories,
+    #[error("FSRS needs exactly 17 weights")]
+    InvalidFsrsWeights,
+    #[error("FSRS desired retention must be between 0 and 1")]
+    InvalidDesiredRetention,
+    #[error("there is no profile named '{0}'")]
+    UnknownProfile(String),
+    #[error(transparent)]
+    Library(#[from] libfukushuu::Error),
     #[cfg(feature = "kittygfx")]
     #[error("Cannot read image")]
-    ImageRead(#[from] io::Error),
+    ImageRead(#[from] std::io::Error),
     #[cfg(feature = "kittygfx")]
     #[error("cannot decode image")]
     ImageDecode(#[from] image::ImageError),
@@ -86,48 +149,287 @@ fn main() -> Result<(), Error> {
     let choices_count = args.choices_count;
     env_logger::Builder::from_env(Env::default().default_filter_or(args.log_level)).init();
 
+    let mut scheduler_config = SchedulerConfig {
+        forced: args.scheduler,
+        ..Default::default()
+    };
+    if !(args.desired_retention > 0.0 && args.desired_retention < 1.0) {
+        println!(
+            "{}",
+            format!(
+                "Desired retention must be between 0 and 1, got {}!",
+                args.desired_retention
+            )
+            .bright_red()
+        );
+        return Err(Error::InvalidDesiredRetention);
+    }
+    scheduler_config.fsrs.desired_retention = args.desired_retention;
+    if let Some(weights) = args.fsrs_weights {
+        match weights.try_into() {
+            Ok(weights) => scheduler_config.fsrs.weights = weights,
+            Err(weights) => {
+                println!(
+                    "{}",
+                    format!("FSRS needs 17 weights, got {}!", weights.len()).bright_red()
+                );
+                return Err(Error::InvalidFsrsWeights);
+            }
+        }
+    }
+
     let db_path = args.db.unwrap_or(PathBuf::from("flashcards.db"));
-    let conn = db::create_or_open(db_path).unwrap();
+    let media_dir = db::media_dir(&db_path, args.media_dir.as_deref());
+    debug!("[Setup] Media directory at {:?}", media_dir);
+    let conn = match db::create_or_open(db_path) {
+        Ok(conn) => conn,
+        Err(err) => {
+            println!("{}", format!("Cannot open database: {}", err).bright_red());
+            return Err(err.into());
+        }
+    };
     debug!("[DB] Database Connection Successful!");
+    // Showing stats should not leave a new profile behind for a misspelled name.
+    let profile = match args.command {
+        Some(Command::Stats { .. }) => {
+            Profile::get_one(&conn, &args.profile).map_err(|err| match err {
+                libfukushuu::Error::Database(rusqlite::Error::QueryReturnedNoRows) => {
+                    Error::UnknownProfile(args.profile.clone())
+                }
+                err => err.into(),
+            })
+        }
+        None => Profile::get_or_create(&conn, &args.profile).map_err(Error::from),
+    };
+    let profile = match profile {
+        Ok(profile) => profile,
+        Err(err) => {
+            println!("{}", format!("Cannot load profile: {}", err).bright_red());
+            return finish(conn, Err(err));
+        }
+    };
+    debug!("[Setup] Using profile {:?}", profile);
 
-    let category = match category(&conn, args.category.as_deref()) {
-        Some(category) => category,
-        None => {
-            warn!("[Setup] No categories found.");
-            println!(
-                "{}",
-                "No categories found. Come back when you have added some cards to the database!"
-                    .yellow()
-            );
-            return finish(conn, Err(Error::NoCategories));
+    if let Some(Command::Stats { json, limit }) = args.command {
+        let stats = match stats::compute(&conn, &profile.name, limit) {
+            Ok(stats) => stats,
+            Err(err) => {
+                println!("{}", format!("Cannot compute stats: {}", err).bright_red());
+                return finish(conn, Err(err.into()));
+            }
+        };
+        if json {
+            println!("{}", serde_json::to_string_pretty(&stats).unwrap());
+        } else {
+            print_stats(&stats);
+        }
+        return finish(conn, Ok(()));
+    }
+
+    let category = if args.due && args.category.is_none() {
+        None
+    } else {
+        match category(&conn, args.category.as_deref()) {
+            Ok(category) => Some(category),
+            Err(err) => {
+                warn!("[Setup] Cannot pick a category: {}", err);
+                println!("{}", format!("Cannot start quiz: {}!", err).yellow());
+                return finish(conn, Err(err.into()));
+            }
         }
     };
     debug!("[Setup] Picked category {:?}", category);
+    let (title, selection) = match category {
+        Some(category) if args.due => (
+            format!("{} (due today)", category.name),
+            Selection::DueToday(Some(category)),
+        ),
+        Some(category) => (category.name.clone(), Selection::RandomPools(category)),
+        None => (String::from("Due today"), Selection::DueToday(None)),
+    };
+
+    let tags = TagFilter {
+        include: args.tags,
+        exclude: args.exclude_tags,
+    };
+    let mode = selection.mode();
+    let category_name = selection.category().map(|category| category.name.clone());
+    let questions = get_question_cards(&conn, &profile.name, question_count, selection, &tags)
+        .and_then(|cards| {
+            debug!("[Setup] Cards: {:?}", cards);
+            init_questions(
+                &conn,
+                &profile.name,
+                cards,
+                choices_count,
+                &scheduler_config,
+                args.direction,
+                &media_dir,
+            )
+        });
+    let mut questions = match questions {
+        Ok(questions) => questions,
+        Err(err) => {
+            println!("{}", format!("Cannot start quiz: {}!", err).bright_red());
+            return finish(conn, Err(err.into()));
+        }
+    };
+    debug!("[Setup] Questions: {:?}", questions.len());
+    if questions.is_empty() {
+        println!("{}", "Nothing to review. Come back later!".yellow());
+        return finish(conn, Ok(()));
+    }
+    let question_count = questions.len() as u32;
     println!(
         "{}",
         format!(
             "==========> {} ({} questions) <==========",
-            category.name, question_count
+            title, question_count
         )
         .cyan()
     );
 
-    let cards = get_question_cards(&conn, question_count, category);
-    debug!("[Setup] Cards: {:?}", cards);
-
-    let mut questions = init_questions(&conn, cards, choices_count).unwrap();
-    debug!("[Setup] Questions: {:?}", questions.len());
+    let session_id = match Session::start(
+        &conn,
+        Session {
+            id: None,
+            profile_name: profile.name.clone(),
+            started_at: db::now_timestamp(),
+            ended_at: None,
+            category_name,
+            mode,
+            question_count,
+            choices_count,
+            correct: 0,
+            incorrect: 0,
+            dont_know: 0,
+            score_delta: 0,
+        },
+    ) {
+        Ok(id) => id,
+        Err(err) => {
+            println!("{}", format!("Cannot start session: {}", err).bright_red());
+            return finish(conn, Err(err.into()));
+        }
+    };
+    questions
+        .iter_mut()
+        .for_each(|question| question.session_id = Some(session_id));
 
+    let player = audio::default_player();
     // INIT DONE
-    #[cfg(feature = "cli")]
-    cli::cli_loop(&conn, questions, question_count, choices_count)?;
-    #[cfg(feature = "gui")]
-    gui::init_gui(&conn, questions, question_count, choices_count)?;
+    cfg_if::cfg_if! {
+        if #[cfg(all(feature = "cli", feature = "gui"))] {
+            let run = if args.cli { run_cli } else { run_gui };
+        } else if #[cfg(feature = "cli")] {
+            let run = run_cli;
+        } else if #[cfg(feature = "gui")] {
+            let run = run_gui;
+        } else {
+            let run = run_without_frontend;
+        }
+    }
+    run(conn, questions, question_count, session_id, player)
+}
+
+/// Quizzes in the terminal.
+#[cfg(feature = "cli")]
+fn run_cli(
+    conn: Connection,
+    questions: Vec<Question>,
+    question_count: u32,
+    session_id: i64,
+    player: Box<dyn AudioPlayer>,
+) -> Result<(), Error> {
+    cli::cli_loop(
+        &conn,
+        questions,
+        question_count,
+        session_id,
+        player.as_ref(),
+    )?;
+    finish(conn, Ok(()))
+}
 
+/// Quizzes in a window. The GUI hands the connection to a worker thread, which
+/// closes it when the window does.
+#[cfg(feature = "gui")]
+fn run_gui(
+    conn: Connection,
+    questions: Vec<Question>,
+    question_count: u32,
+    session_id: i64,
+    player: Box<dyn AudioPlayer>,
+) -> Result<(), Error> {
+    gui::init_gui(conn, questions, question_count, session_id, player)
+}
+
+/// Built without a frontend, there is nothing to quiz with.
+#[cfg(not(any(feature = "cli", feature = "gui")))]
+fn run_without_frontend(
+    conn: Connection,
+    _questions: Vec<Question>,
+    _question_count: u32,
+    _session_id: i64,
+    _player: Box<dyn AudioPlayer>,
+) -> Result<(), Error> {
     finish(conn, Ok(()))
 }
 
+fn print_stats(stats: &Stats) {
+    println!(
+        "{}",
+        format!("==========> Stats for {} <==========", stats.profile_name).cyan()
+    );
+    println!(
+        "Streak: {} days (longest: {} days)",
+        stats.current_streak, stats.longest_streak
+    );
+
+    for (heading, accuracies) in [("category", &stats.categories), ("pool", &stats.pools)] {
+        println!("{}", format!("Accuracy per {}:", heading).cyan());
+        if accuracies.is_empty() {
+            println!("  Nothing answered yet.");
+        }
+        for accuracy in accuracies {
+            println!(
+                "  {}: {:.0}% ({}/{})",
+                accuracy.name,
+                accuracy.accuracy * 100.0,
+                accuracy.correct,
+                accuracy.answered
+            );
+        }
+    }
+
+    println!("{}", "Weakest cards:".cyan());
+    for card in &stats.weakest_cards {
+        let score = format!("{:+}", card.score);
+        let score = if card.score < 0 {
+            score.bright_red()
+        } else {
+            score.normal()
+        };
+        println!("  {} {} ({})", score, card.front, card.back);
+    }
+
+    println!(
+        "{}",
+        format!("Never answered: {} cards", stats.never_answered_count).cyan()
+    );
+    for card in &stats.never_answered {
+        println!("  {} ({})", card.front, card.back);
+    }
+
+    println!("{}", "Answers per day:".cyan());
+    for day in &stats.daily {
+        println!("  {}: {} ({} correct)", day.date, day.answered, day.correct);
+    }
+}
+
 fn finish(conn: Connection, to_error: Result<(), Error>) -> Result<(), Error> {
-    db::close_db(conn).unwrap();
+    if let Err(err) = db::close_db(conn) {
+        println!("{}", format!("Cannot close database: {}", err).bright_red());
+    }
     to_error
 }
@@ -1,19 +1,24 @@
 use clap::{Parser, Subcommand};
 use colored::Colorize;
 use env_logger::Env;
-use log::{error, info};
-use rusqlite::Error;
+use libfukushuu::db::{
+    self, Card, CardInserter, Category, Media, Note, NoteType, Pool, Profile, Schedule, Tag, Trash,
+    TrashedItem,
+};
+use libfukushuu::scheduler::{Progress, SchedulerKind};
+use libfukushuu::shitsumon::Direction;
+use libfukushuu::{doctor, Error};
+use log::{debug, error, info, warn};
+use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
-use std::fmt::format;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
-mod libfukushuu;
-use crate::libfukushuu::db;
-use crate::libfukushuu::db::{Card, Category, Pool};
 
 #[derive(Parser, Debug)]
 #[command(name = "入出力者 (Nyūshutsuryokusha)")]
 #[command(version, about, long_about = None)]
+#[command(subcommand_precedence_over_arg = true)]
 struct Args {
     #[command(subcommand)]
     command: Commands,
@@ -24,45 +29,135 @@ struct Args {
     refresh_db: bool,
     #[arg(short, long, value_name = "FILE", default_value = "flashcards.db")]
     db: Option<PathBuf>,
+    /// Whose progress (`score` and `schedule` of each card) to import or export.
+    #[arg(short, long, default_value = db::DEFAULT_PROFILE)]
+    profile: String,
+    /// Directory that relative image and sound paths are found in. Defaults to the one the database is in.
+    #[arg(long, value_name = "DIR")]
+    media_dir: Option<PathBuf>,
 
     json: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Import,
+    Import {
+        /// Keep everything that imported cleanly instead of rolling back when some items fail.
+        #[arg(long, default_value = "false")]
+        partial: bool,
+        /// Copy the images the cards use into the database, so they work without the files.
+        #[arg(long, default_value = "false")]
+        ingest_media: bool,
+    },
     Export,
+    /// Check the database for problems.
+    Doctor {
+        /// Options per question the quiz is run with, pools with fewer cards are reported.
+        #[arg(short, long, default_value = "4")]
+        choices_count: u32,
+        /// Repair the problems that can be repaired without losing data.
+        #[arg(long, default_value = "false")]
+        fix: bool,
+    },
+    /// Find cards whose front or back contains the given text.
+    Search {
+        query: String,
+        #[arg(short, long, default_value = "50")]
+        limit: u32,
+    },
+    /// Move cards, pools and categories to the trash, or list, restore and empty it.
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TrashAction {
+    /// Move a card to the trash.
+    Card { id: i32 },
+    /// Move a pool and its cards to the trash.
+    Pool { id: i32 },
+    /// Move a category to the trash, with everything nested in it.
+    Category { name: String },
+    /// Show what is in the trash.
+    List,
+    /// Take an entry out of the trash again.
+    Restore { id: i64 },
+    /// Delete everything in the trash for good.
+    Empty,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct FukushuuJson {
+    /// Imported before the categories, so that their notes can use them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    note_types: Vec<NoteType>,
     categories: Vec<CategoryJson>,
 }
 #[derive(Serialize, Deserialize, Debug)]
 struct CategoryJson {
     name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scheduler: Option<SchedulerKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    direction: Option<Direction>,
     pools: Vec<PoolJson>,
+    /// Categories nested in this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    categories: Vec<CategoryJson>,
 }
 impl CategoryJson {
     fn from(cate: &Category) -> CategoryJson {
         CategoryJson {
             name: cate.name.clone(),
+            scheduler: cate.scheduler,
+            direction: cate.direction,
             pools: vec![],
+            categories: vec![],
         }
     }
+
+    /// Describes what is nested in the category, for reporting what a failure skipped.
+    fn contents(&self) -> String {
+        fn count(category: &CategoryJson, totals: &mut [usize; 4]) {
+            totals[0] += category.categories.len();
+            totals[1] += category.pools.len();
+            category.pools.iter().for_each(|pool| {
+                totals[2] += pool.cards.len();
+                totals[3] += pool.notes.len();
+            });
+            category
+                .categories
+                .iter()
+                .for_each(|child| count(child, totals));
+        }
+        let mut totals = [0; 4];
+        count(self, &mut totals);
+        format!(
+            "{} Subcategories, {} Pools, {} Cards and {} Notes",
+            totals[0], totals[1], totals[2], totals[3]
+        )
+    }
 }
 #[derive(Serialize, Deserialize, Debug)]
 struct PoolJson {
-    id: i32,
+    /// Left out to add a new pool, with an id picked by the database.
+    id: Option<i32>,
     category_name: Option<String>,
+    #[serde(default)]
     cards: Vec<CardJson>,
+    /// Entries of a note type, each generating its cards in the pool.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<NoteJson>,
 }
 impl PoolJson {
     fn from(pool: &Pool) -> PoolJson {
         PoolJson {
-            id: pool.id,
+            id: Some(pool.id),
             category_name: pool.category_name.clone(),
             cards: vec![],
+            notes: vec![],
         }
     }
 }
@@ -73,7 +168,37 @@ struct CardJson {
     back: Option<String>,
     front_image: Option<PathBuf>,
     back_image: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    front_audio: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    back_audio: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reading: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    example: Option<String>,
     score: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schedule: Option<Schedule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+struct NoteJson {
+    /// Given to update the fields of a note imported before, regenerating its cards.
+    id: Option<i32>,
+    note_type: String,
+    fields: BTreeMap<String, String>,
+}
+impl NoteJson {
+    fn from(note: &Note) -> NoteJson {
+        NoteJson {
+            id: note.id,
+            note_type: note.note_type_name.clone(),
+            fields: note.fields.clone(),
+        }
+    }
 }
 macro_rules! empty_none_or_some {
     ($condition: expr, $some_value: expr) => {
@@ -89,20 +214,90 @@ impl CardJson {
             id: card.id,
             front: empty_none_or_some!(card.front.is_empty(), card.front.clone()),
             back: empty_none_or_some!(card.back.is_empty(), card.back.clone()),
-            front_image: empty_none_or_some!(card.front_image.clone().into_os_string().is_empty(), card.front_image.clone()),
-            back_image: empty_none_or_some!(card.back_image.clone().into_os_string().is_empty(), card.back_image.clone()),
-            score: card.score,
+            front_image: empty_none_or_some!(
+                card.front_image.clone().into_os_string().is_empty(),
+                card.front_image.clone()
+            ),
+            back_image: empty_none_or_some!(
+                card.back_image.clone().into_os_string().is_empty(),
+                card.back_image.clone()
+            ),
+            front_audio: empty_none_or_some!(
+                card.front_audio.as_os_str().is_empty(),
+                card.front_audio.clone()
+            ),
+            back_audio: empty_none_or_some!(
+                card.back_audio.as_os_str().is_empty(),
+                card.back_audio.clone()
+            ),
+            reading: empty_none_or_some!(card.reading.is_empty(), card.reading.clone()),
+            notes: empty_none_or_some!(card.notes.is_empty(), card.notes.clone()),
+            example: empty_none_or_some!(card.example.is_empty(), card.example.clone()),
+            score: None,
+            schedule: None,
+            tags: vec![],
         }
     }
 }
 
+/// What an import did, printed once it is finished.
+#[derive(Debug, Default)]
+struct ImportSummary {
+    categories_created: usize,
+    categories_existing: usize,
+    pools_created: usize,
+    pools_existing: usize,
+    cards_created: usize,
+    note_types_created: usize,
+    note_types_updated: usize,
+    notes_created: usize,
+    notes_updated: usize,
+    failures: Vec<String>,
+}
+impl ImportSummary {
+    fn print(&self) {
+        info!("{}", "Import summary:".cyan());
+        info!(
+            "  Categories: {} created, {} already existed",
+            self.categories_created, self.categories_existing
+        );
+        info!(
+            "  Pools: {} created, {} already existed",
+            self.pools_created, self.pools_existing
+        );
+        info!("  Cards: {} created", self.cards_created);
+        info!(
+            "  Note types: {} created, {} updated",
+            self.note_types_created, self.note_types_updated
+        );
+        info!(
+            "  Notes: {} created, {} updated",
+            self.notes_created, self.notes_updated
+        );
+        info!("  Failed: {}", self.failures.len());
+        self.failures.iter().for_each(|failure| {
+            error!("{}", format!("  ✘ {}", failure).red());
+        });
+    }
+}
+
+/// Where [`import_category`] looks for card images, and whether it copies them into the
+/// database.
+struct MediaImport<'a> {
+    dir: &'a Path,
+    ingest: bool,
+}
+
 fn main() {
     let args = Args::parse();
     env_logger::Builder::from_env(Env::default().default_filter_or(args.log_level)).init();
 
-    let json_file = match args.json {
-        Some(f) => f,
-        None => {
+    let json_file = match (&args.command, args.json) {
+        (Commands::Doctor { .. } | Commands::Search { .. } | Commands::Trash { .. }, _) => {
+            PathBuf::new()
+        }
+        (_, Some(f)) => f,
+        (_, None) => {
             error!("{}", "From file not specified!".red());
             std::process::exit(1);
         }
@@ -118,6 +313,8 @@ fn main() {
         "{}",
         format!("File at {:?} and Database at {:?}", json_file, db_path).cyan()
     );
+    let media_dir = db::media_dir(&db_path, args.media_dir.as_deref());
+    debug!("Media directory at {:?}", media_dir);
     let db = match db::create_or_open(db_path) {
         Ok(d) => d,
         Err(e) => {
@@ -127,8 +324,21 @@ fn main() {
     };
 
     match args.command {
-        Commands::Import => {
-            let json = std::fs::read_to_string(json_file).unwrap();
+        Commands::Import {
+            partial,
+            ingest_media,
+        } => {
+            let json = match std::fs::read_to_string(&json_file) {
+                Ok(j) => j,
+                Err(error) => {
+                    error!(
+                        "{}",
+                        format!("Unable to read {:?}: {}!", json_file, error).red()
+                    );
+                    db::close_db(db).unwrap();
+                    std::process::exit(1);
+                }
+            };
             let content: FukushuuJson = match serde_json::from_str(json.as_str()) {
                 Ok(c) => c,
                 Err(error) => {
@@ -147,135 +357,651 @@ fn main() {
                 .blue()
             );
 
-            macro_rules! check_exists {
-                ($to_match: expr, $err_msg: expr) => {
-                    match $to_match {
-                        Ok(_) => true,
-                        Err(e) => match e {
-                            Error::QueryReturnedNoRows => false,
-                            _ => {
-                                error!("{}", format!($err_msg, e).red());
-                                false
-                            }
-                        },
-                    }
-                };
+            let mut summary = ImportSummary::default();
+            let tx = match db.unchecked_transaction() {
+                Ok(tx) => tx,
+                Err(e) => {
+                    error!("{}{}", "Unable to start import: ".red(), e);
+                    std::process::exit(1);
+                }
+            };
+            if let Err(e) = Profile::get_or_create(&tx, &args.profile) {
+                error!("{}{}", "Unable to load Profile: ".red(), e);
+                std::process::exit(1);
             }
-
+            let media = MediaImport {
+                dir: &media_dir,
+                ingest: ingest_media,
+            };
+            content.note_types.iter().for_each(|note_type| {
+                import_note_type(&tx, note_type, &mut summary);
+            });
             content.categories.iter().for_each(|category| {
-                info!(
+                import_category(&tx, category, None, &args.profile, &media, 0, &mut summary);
+            });
+            summary.print();
+
+            if summary.failures.is_empty() || partial {
+                if let Err(e) = tx.commit() {
+                    error!("{}{}", "Unable to commit import: ".red(), e);
+                    db::close_db(db).unwrap();
+                    std::process::exit(1);
+                }
+                info!("{}", "Import Complete!".green());
+            } else {
+                tx.rollback().unwrap();
+                error!(
                     "{}",
                     format!(
-                        "├ Category: {} ({} Pools)",
-                        category.name,
-                        category.pools.len()
+                        "Import failed, nothing was changed. Pass --partial to keep the {} items that imported.",
+                        summary.categories_created + summary.pools_created + summary.cards_created
                     )
-                    .blue()
+                    .red()
                 );
-                let category_exists = check_exists!(
-                    Category::get_one(&db, &category.name),
-                    "Error accessing Categories: {}!"
+                db::close_db(db).unwrap();
+                std::process::exit(1);
+            }
+        }
+        Commands::Export => {
+            let start = Instant::now();
+
+            let exported = Category::get_roots(&db).and_then(|root_categories| {
+                let mut exported = FukushuuJson {
+                    note_types: NoteType::get_all(&db)?,
+                    categories: vec![],
+                };
+                for (i, category) in root_categories.iter().enumerate() {
+                    info!(
+                        "{}",
+                        format!("Exporting Category {}/{}", i + 1, root_categories.len()).blue()
+                    );
+                    exported
+                        .categories
+                        .push(export_category(&db, category, &args.profile, 1)?);
+                }
+                Ok(exported)
+            });
+            let exported = match exported {
+                Ok(e) => e,
+                Err(e) => {
+                    error!("{}{}", "Unable to export Database: ".red(), e);
+                    db::close_db(db).unwrap();
+                    std::process::exit(1);
+                }
+            };
+
+            let json_exported = serde_json::to_string(&exported).unwrap();
+            if let Err(e) = std::fs::write(&json_file, json_exported) {
+                error!(
+                    "{}",
+                    format!("Unable to write {:?}: {}!", json_file, e).red()
                 );
-                if !category_exists {
-                    Category::add(
-                        &db,
-                        Category {
-                            name: category.name.clone(),
-                        },
+                db::close_db(db).unwrap();
+                std::process::exit(1);
+            }
+            info!(
+                "{}",
+                format!("Export Complete in {} ms!", start.elapsed().as_millis()).green()
+            );
+        }
+        Commands::Search { query, limit } => {
+            let cards = match Card::search(&db, &query, limit) {
+                Ok(c) => c,
+                Err(e) => {
+                    error!("{}{}", "Unable to search Cards: ".red(), e);
+                    db::close_db(db).unwrap();
+                    std::process::exit(1);
+                }
+            };
+            cards.iter().for_each(|card| {
+                info!(
+                    "{} {}",
+                    format!(
+                        "[{} / Pool {}]",
+                        card.category_name.as_deref().unwrap_or("-"),
+                        card.pool_id.map_or("-".to_string(), |id| id.to_string())
+                    )
+                    .blue(),
+                    format!(
+                        "Card {}: {} → {}",
+                        card.id.unwrap_or_default(),
+                        card.front,
+                        card.back
                     )
-                        .unwrap();
+                    .green()
+                );
+            });
+            info!(
+                "{}",
+                format!("{} matches for {:?}", cards.len(), query).cyan()
+            );
+        }
+        Commands::Doctor { choices_count, fix } => {
+            let findings = if fix {
+                doctor::diagnose_and_fix(&db, choices_count, &media_dir)
+            } else {
+                doctor::diagnose(&db, choices_count, &media_dir)
+                    .map(|findings| findings.into_iter().map(|f| (f, false)).collect())
+            };
+            let findings = match findings {
+                Ok(f) => f,
+                Err(e) => {
+                    error!("{}{}", "Unable to check Database: ".red(), e);
+                    db::close_db(db).unwrap();
+                    std::process::exit(1);
                 }
+            };
 
-                category.pools.iter().for_each(|pool| {
+            findings
+                .iter()
+                .for_each(|(finding, fixed)| match (fixed, finding.is_fixable()) {
+                    (true, _) => info!("{}", format!("✔ Fixed: {}", finding).green()),
+                    (false, true) => {
+                        info!("{}", format!("✘ {} (fixable with --fix)", finding).yellow())
+                    }
+                    (false, false) => error!("{}", format!("✘ {}", finding).red()),
+                });
+            let fixed = findings.iter().filter(|(_, fixed)| *fixed).count();
+            if findings.is_empty() {
+                info!("{}", "No problems found!".green());
+            } else {
+                info!(
+                    "{}",
+                    format!("{} problems found, {} fixed.", findings.len(), fixed).cyan()
+                );
+            }
+        }
+        Commands::Trash { action } => {
+            let done = match action {
+                TrashAction::Card { id } => Card::trash(&db, id).map(|entry| {
                     info!(
                         "{}",
-                        format!("│ ├ Pool: {} ({} Cards)", pool.id, pool.cards.len()).blue()
-                    );
-                    let pool_exists =
-                        check_exists!(Pool::get_by_id(&db, pool.id), "Error accessing Pools: {}!");
-                    if !pool_exists {
-                        Pool::add(
-                            &db,
-                            Pool {
-                                id: pool.id,
-                                category_name: Some(category.name.clone()),
-                            },
-                        )
-                            .unwrap();
-                    }
-                    pool.cards.iter().for_each(|card| {
-                        if validate_card(card) {
-                            Card::add(
-                                &db,
-                                Card {
-                                    id: card.id,
-                                    front: card.front.clone().unwrap_or_default(),
-                                    back: card.back.clone().unwrap_or_default(),
-                                    front_image: card.front_image.clone().unwrap_or_default(),
-                                    back_image: card.back_image.clone().unwrap_or_default(),
-                                    score: card.score,
-                                    pool_id: Some(pool.id),
-                                    category_name: Some(category.name.clone()),
-                                },
-                            )
-                                .unwrap();
-                            info!("{} {}", "│ │".blue(), format!("├ Card: {:?}", card).green());
-                        } else {
-                            error!(
-                                "{} {}",
-                                "│ │".blue(),
-                                format!(
-                            "├ ✘ Card: {:?} (Missing `front`&`front_image` or `back`&`back_image`)",
-                            card
+                        format!("Moved Card {} to the trash as entry {}.", id, entry).green()
+                    )
+                }),
+                TrashAction::Pool { id } => Pool::trash(&db, id).map(|entry| {
+                    info!(
+                        "{}",
+                        format!("Moved Pool {} to the trash as entry {}.", id, entry).green()
+                    )
+                }),
+                TrashAction::Category { name } => Category::trash(&db, &name).map(|entry| {
+                    info!(
+                        "{}",
+                        format!("Moved Category '{}' to the trash as entry {}.", name, entry)
+                            .green()
+                    )
+                }),
+                TrashAction::List => Trash::get_all(&db).map(|entries| {
+                    entries.iter().for_each(|entry| {
+                        info!(
+                            "{} {}",
+                            format!("[{}]", entry.id).blue(),
+                            format!("{} ({} Cards)", entry.item, entry.card_count).yellow()
                         )
-                                .red()
-                                .strikethrough()
-                            );
-                        }
                     });
-                });
-            });
+                    info!(
+                        "{}",
+                        format!("{} entries in the trash", entries.len()).cyan()
+                    );
+                }),
+                TrashAction::Restore { id } => Trash::restore(&db, id)
+                    .map(|_| info!("{}", format!("Restored trash entry {}.", id).green())),
+                TrashAction::Empty => Trash::empty(&db).map(|cards| {
+                    info!(
+                        "{}",
+                        format!("Emptied the trash, deleting {} Cards.", cards).green()
+                    )
+                }),
+            };
+            if let Err(e) = done {
+                let e = match e {
+                    Error::Database(rusqlite::Error::QueryReturnedNoRows) => {
+                        "not found (or already in the trash)".to_string()
+                    }
+                    e => e.to_string(),
+                };
+                error!("{}{}", "Unable to update the trash: ".red(), e);
+                db::close_db(db).unwrap();
+                std::process::exit(1);
+            }
         }
-        Commands::Export => {
-            let start = Instant::now();
+    }
 
-            let mut exported = FukushuuJson {
-                categories: vec![],
-            };
+    db::close_db(db).unwrap()
+}
 
-            let available_categories = Category::get_all(&db).unwrap();
-            available_categories.iter().enumerate().for_each(|(i, category)| {
-                info!("{}", format!("Exporting Category {}/{}", i + 1, available_categories.len()).blue());
-                let mut category = CategoryJson::from(category);
-                let pools_in_category = Pool::get_all_in_category(&db, &category.name).unwrap();
-                pools_in_category.iter().enumerate().for_each(|(j, pool)| {
-                    info!("  {}", format!("Exporting Pool {}/{}", j + 1, pools_in_category.len()).blue());
-                    let mut pool = PoolJson::from(pool);
-                    let cards_in_pool = Card::get_in_pool(&db, pool.id).unwrap();
-                    cards_in_pool.iter().enumerate().for_each(|(k, card)| {
-                        info!("    {}", format!("Exporting Card {}/{}", k + 1, cards_in_pool.len()).green());
-                        pool.cards.push(CardJson::from(card));
-                    });
-                    category.pools.push(pool);
+macro_rules! check_exists {
+    ($to_match: expr, $err_msg: expr) => {
+        match $to_match {
+            Ok(_) => true,
+            Err(e) => match e {
+                Error::Database(rusqlite::Error::QueryReturnedNoRows) => false,
+                _ => {
+                    error!("{}", format!($err_msg, e).red());
+                    false
+                }
+            },
+        }
+    };
+}
+
+/// The error for importing into `item`, which has to be restored from the trash first.
+fn in_trash_error(item: TrashedItem) -> Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT),
+        Some(format!("{} is in the trash, restore it first", item)),
+    )
+    .into()
+}
+
+/// Adds `note_type`, or replaces the fields and templates of the one with its name,
+/// which regenerates the cards of its notes.
+fn import_note_type(db: &Connection, note_type: &NoteType, summary: &mut ImportSummary) {
+    let exists = check_exists!(
+        NoteType::get_one(db, &note_type.name),
+        "Error accessing Note Types: {}!"
+    );
+    let imported = if exists {
+        NoteType::update(db, note_type).map(|_| summary.note_types_updated += 1)
+    } else {
+        NoteType::add(db, note_type).map(|_| summary.note_types_created += 1)
+    };
+    match imported {
+        Ok(_) => info!(
+            "{}",
+            format!(
+                "├ Note Type: {} ({} Templates)",
+                note_type.name,
+                note_type.templates.len()
+            )
+            .blue()
+        ),
+        Err(e) => {
+            error!(
+                "{}",
+                format!("├ ✘ Note Type: {} ({})", note_type.name, e).red()
+            );
+            summary
+                .failures
+                .push(format!("Note Type {}: {}", note_type.name, e));
+        }
+    }
+}
+
+/// Imports `category`, its pools with their cards and notes, then its subcategories.
+/// Progress on the cards is saved to `profile_name`. Card images are looked up as set in
+/// `media`, and stored in the database if it says so. Failures are recorded in `summary`
+/// instead of stopping the import.
+fn import_category(
+    db: &Connection,
+    category: &CategoryJson,
+    parent_name: Option<&str>,
+    profile_name: &str,
+    media: &MediaImport,
+    depth: usize,
+    summary: &mut ImportSummary,
+) {
+    let indent = "│ ".repeat(depth);
+    info!(
+        "{}",
+        format!(
+            "{}├ Category: {} ({} Pools, {} Subcategories)",
+            indent,
+            category.name,
+            category.pools.len(),
+            category.categories.len()
+        )
+        .blue()
+    );
+    let category_exists = check_exists!(
+        Category::get_one(db, &category.name),
+        "Error accessing Categories: {}!"
+    );
+    let imported = if !category_exists {
+        Category::add(
+            db,
+            Category {
+                name: category.name.clone(),
+                scheduler: category.scheduler,
+                parent_name: parent_name.map(String::from),
+                direction: category.direction,
+            },
+        )
+        .map(|_| summary.categories_created += 1)
+    } else {
+        db::atomically(db, || {
+            if Category::in_trash(db, &category.name)? {
+                return Err(in_trash_error(TrashedItem::Category(category.name.clone())));
+            }
+            if category.scheduler.is_some() {
+                Category::set_scheduler(db, &category.name, category.scheduler)?;
+            }
+            if category.direction.is_some() {
+                Category::set_direction(db, &category.name, category.direction)?;
+            }
+            if parent_name.is_some() {
+                Category::set_parent(db, &category.name, parent_name)?;
+            }
+            Ok(())
+        })
+        .map(|_| summary.categories_existing += 1)
+    };
+    if let Err(e) = imported {
+        error!(
+            "{}",
+            format!("{}├ ✘ Category: {} ({})", indent, category.name, e).red()
+        );
+        summary.failures.push(format!(
+            "Category {}: {} (skipped its {})",
+            category.name,
+            e,
+            category.contents()
+        ));
+        return;
+    }
+
+    category.pools.iter().for_each(|pool| {
+        let label = pool.id.map_or("(new)".to_string(), |id| id.to_string());
+        info!(
+            "{}",
+            format!("{}│ ├ Pool: {} ({} Cards)", indent, label, pool.cards.len()).blue()
+        );
+        let existing = pool
+            .id
+            .filter(|&id| check_exists!(Pool::get_by_id(db, id), "Error accessing Pools: {}!"));
+        let added = match existing {
+            Some(id) => Pool::in_trash(db, id).and_then(|trashed| {
+                if trashed {
+                    return Err(in_trash_error(TrashedItem::Pool(id)));
+                }
+                summary.pools_existing += 1;
+                Ok(id)
+            }),
+            None => Pool::create(db, pool.id, Some(category.name.clone())).map(|created| {
+                summary.pools_created += 1;
+                created.id
+            }),
+        };
+        let pool_id = match added {
+            Ok(id) => id,
+            Err(e) => {
+                error!(
+                    "{}",
+                    format!("{}│ ├ ✘ Pool: {} ({})", indent, label, e).red()
+                );
+                summary.failures.push(format!(
+                    "Pool {}: {} (skipped its {} Cards and {} Notes)",
+                    label,
+                    e,
+                    pool.cards.len(),
+                    pool.notes.len()
+                ));
+                return;
+            }
+        };
+        let mut inserter = match CardInserter::new(db) {
+            Ok(inserter) => inserter,
+            Err(e) => {
+                error!(
+                    "{}",
+                    format!("{}│ ├ ✘ Pool: {} ({})", indent, pool_id, e).red()
+                );
+                summary.failures.push(format!(
+                    "Pool {}: {} (skipped its {} Cards and {} Notes)",
+                    pool_id,
+                    e,
+                    pool.cards.len(),
+                    pool.notes.len()
+                ));
+                return;
+            }
+        };
+        pool.cards.iter().for_each(|card| {
+            if !validate_card(card) {
+                error!(
+                    "{} {}",
+                    format!("{}│ │", indent).blue(),
+                    format!(
+                        "├ ✘ Card: {:?} (Missing `front`&`front_image` or `back`&`back_image`)",
+                        card
+                    )
+                    .red()
+                    .strikethrough()
+                );
+                summary.failures.push(format!(
+                    "Card {:?} in Pool {}: missing `front`&`front_image` or `back`&`back_image`",
+                    card.front.as_deref().unwrap_or_default(),
+                    pool_id
+                ));
+                return;
+            }
+            // Ingested images are read right after, which reports them if they are missing.
+            [&card.front_image, &card.back_image]
+                .into_iter()
+                .filter(|_| !media.ingest)
+                .chain([&card.front_audio, &card.back_audio])
+                .flatten()
+                .filter(|path| !path.as_os_str().is_empty())
+                .map(|path| db::resolve_path(media.dir, path))
+                .filter(|path| !path.exists())
+                .for_each(|path| {
+                    warn!(
+                        "{} {}",
+                        format!("{}│ │", indent).blue(),
+                        format!("├ Card: {:?} uses missing file {:?}", card, path).yellow()
+                    )
                 });
-                exported.categories.push(category);
+            let images = if media.ingest {
+                read_image(media.dir, &card.front_image).and_then(|front| {
+                    read_image(media.dir, &card.back_image).map(|back| (front, back))
+                })
+            } else {
+                Ok((None, None))
+            };
+            let (front_data, back_data) = match images {
+                Ok(images) => images,
+                Err(e) => {
+                    error!(
+                        "{} {}",
+                        format!("{}│ │", indent).blue(),
+                        format!("├ ✘ Card: {:?} (Cannot read image: {})", card, e).red()
+                    );
+                    summary.failures.push(format!(
+                        "Card {:?} in Pool {}: cannot read image: {}",
+                        card.front.as_deref().unwrap_or_default(),
+                        pool_id,
+                        e
+                    ));
+                    return;
+                }
+            };
+            let added = db::atomically(db, || {
+                let front_media = front_data.map(|data| Media::add(db, &data)).transpose()?;
+                let back_media = back_data.map(|data| Media::add(db, &data)).transpose()?;
+                let card_id = inserter.insert(&Card {
+                    id: card.id,
+                    front: card.front.clone().unwrap_or_default(),
+                    back: card.back.clone().unwrap_or_default(),
+                    front_image: card.front_image.clone().unwrap_or_default(),
+                    back_image: card.back_image.clone().unwrap_or_default(),
+                    pool_id: Some(pool_id),
+                    category_name: Some(category.name.clone()),
+                    front_media,
+                    back_media,
+                    front_audio: card.front_audio.clone().unwrap_or_default(),
+                    back_audio: card.back_audio.clone().unwrap_or_default(),
+                    reading: card.reading.clone().unwrap_or_default(),
+                    notes: card.notes.clone().unwrap_or_default(),
+                    example: card.example.clone().unwrap_or_default(),
+                })?;
+                if card.score.is_some() || card.schedule.is_some() {
+                    Card::set_progress(
+                        db,
+                        profile_name,
+                        card_id,
+                        &Progress {
+                            score: card.score.unwrap_or(0),
+                            schedule: card.schedule.unwrap_or_default(),
+                        },
+                    )?;
+                }
+                card.tags
+                    .iter()
+                    .try_for_each(|tag| Card::add_tag(db, card_id, tag))
             });
+            match added {
+                Ok(_) => {
+                    summary.cards_created += 1;
+                    info!(
+                        "{} {}",
+                        format!("{}│ │", indent).blue(),
+                        format!("├ Card: {:?}", card).green()
+                    );
+                }
+                Err(e) => {
+                    error!(
+                        "{} {}",
+                        format!("{}│ │", indent).blue(),
+                        format!("├ ✘ Card: {:?} ({})", card, e).red()
+                    );
+                    summary.failures.push(format!(
+                        "Card {:?} in Pool {}: {}",
+                        card.front.as_deref().unwrap_or_default(),
+                        pool_id,
+                        e
+                    ));
+                }
+            }
+        });
+        pool.notes.iter().for_each(|note| {
+            let existing = note
+                .id
+                .filter(|&id| check_exists!(Note::get_by_id(db, id), "Error accessing Notes: {}!"));
+            let imported = match existing {
+                Some(id) => {
+                    Note::update_fields(db, id, &note.fields).map(|_| summary.notes_updated += 1)
+                }
+                None => Note::add(
+                    db,
+                    &Note {
+                        id: note.id,
+                        note_type_name: note.note_type.clone(),
+                        pool_id,
+                        fields: note.fields.clone(),
+                    },
+                )
+                .map(|_| summary.notes_created += 1),
+            };
+            match imported {
+                Ok(_) => info!(
+                    "{} {}",
+                    format!("{}│ │", indent).blue(),
+                    format!("├ Note: {} {:?}", note.note_type, note.fields).green()
+                ),
+                Err(e) => {
+                    error!(
+                        "{} {}",
+                        format!("{}│ │", indent).blue(),
+                        format!("├ ✘ Note: {} {:?} ({})", note.note_type, note.fields, e).red()
+                    );
+                    summary
+                        .failures
+                        .push(format!("Note {:?} in Pool {}: {}", note.fields, pool_id, e));
+                }
+            }
+        });
+    });
 
-            let json_exported = serde_json::to_string(&exported).unwrap();
-            std::fs::write(json_file, json_exported).unwrap();
-            info!("{}", format!("Export Complete in {} ms!", start.elapsed().as_millis()).green());
+    category.categories.iter().for_each(|child| {
+        import_category(
+            db,
+            child,
+            Some(&category.name),
+            profile_name,
+            media,
+            depth + 1,
+            summary,
+        );
+    });
+}
+
+fn export_category(
+    db: &Connection,
+    category: &Category,
+    profile_name: &str,
+    depth: usize,
+) -> libfukushuu::Result<CategoryJson> {
+    let indent = "  ".repeat(depth);
+    let mut category_json = CategoryJson::from(category);
+    let pools_in_category = Pool::get_all_in_category(db, &category.name)?;
+    for (j, pool) in pools_in_category.iter().enumerate() {
+        info!(
+            "{}{}",
+            indent,
+            format!("Exporting Pool {}/{}", j + 1, pools_in_category.len()).blue()
+        );
+        let pool_id = pool.id;
+        let mut pool = PoolJson::from(pool);
+        // Cards generated from notes come back when the notes are imported.
+        let generated = Note::generated_in_pool(db, pool_id)?;
+        let cards_in_pool: Vec<Card> = Card::get_in_pool(db, pool_id)?
+            .into_iter()
+            .filter(|card| !card.id.is_some_and(|id| generated.contains_key(&id)))
+            .collect();
+        for (k, card) in cards_in_pool.iter().enumerate() {
+            info!(
+                "{}  {}",
+                indent,
+                format!("Exporting Card {}/{}", k + 1, cards_in_pool.len()).green()
+            );
+            let mut card_json = CardJson::from(card);
+            if let Some(id) = card.id {
+                card_json.tags = Tag::get_for_card(db, id)?
+                    .into_iter()
+                    .map(|tag| tag.name)
+                    .collect();
+                let progress = Card::get_progress(db, profile_name, id)?;
+                if progress.score != 0 || progress.schedule != Schedule::default() {
+                    card_json.score = Some(progress.score);
+                    card_json.schedule = Some(progress.schedule);
+                }
+            }
+            pool.cards.push(card_json);
         }
+        pool.notes = Note::get_in_pool(db, pool_id)?
+            .iter()
+            .map(NoteJson::from)
+            .collect();
+        category_json.pools.push(pool);
     }
 
-    db::close_db(db).unwrap()
+    let children = Category::get_children(db, &category.name)?;
+    for (i, child) in children.iter().enumerate() {
+        info!(
+            "{}{}",
+            indent,
+            format!("Exporting Subcategory {}/{}", i + 1, children.len()).blue()
+        );
+        category_json
+            .categories
+            .push(export_category(db, child, profile_name, depth + 1)?);
+    }
+
+    Ok(category_json)
 }
 
-fn validate_card(card: &CardJson) -> bool {
-    if (!card.front.is_none() | !card.front_image.is_none())
-        && (!card.back.is_none() | !card.back_image.is_none())
-    {
-        true
-    } else {
-        false
+/// The contents of the image file at `path` in `media_dir`, if there is one.
+fn read_image(media_dir: &Path, path: &Option<PathBuf>) -> std::io::Result<Option<Vec<u8>>> {
+    match path {
+        Some(path) if !path.as_os_str().is_empty() => {
+            std::fs::read(db::resolve_path(media_dir, path)).map(Some)
+        }
+        _ => Ok(None),
     }
 }
+
+fn validate_card(card: &CardJson) -> bool {
+    (card.front.is_some() || card.front_image.is_some())
+        && (card.back.is_some() || card.back_image.is_some())
+}
@@ -0,0 +1,38 @@
+use libfukushuu::db::{self, Card, Category, Pool};
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+pub const PROFILE: &str = db::DEFAULT_PROFILE;
+
+pub fn card(front: &str, back: &str, pool_id: i32, category_name: &str) -> Card {
+    Card {
+        id: None,
+        front: front.to_string(),
+        back: back.to_string(),
+        front_image: PathBuf::new(),
+        back_image: PathBuf::new(),
+        pool_id: Some(pool_id),
+        category_name: Some(category_name.to_string()),
+        front_media: None,
+        back_media: None,
+        front_audio: PathBuf::new(),
+        back_audio: PathBuf::new(),
+        reading: String::new(),
+        notes: String::new(),
+        example: String::new(),
+    }
+}
+
+/// "Animals" with four cards in pool 1, and "Lonely" with a single card in pool 2.
+pub fn setup() -> Connection {
+    let conn = db::open_in_memory().unwrap();
+    Category::create(&conn, "Animals".to_string(), None, None).unwrap();
+    Category::create(&conn, "Lonely".to_string(), None, None).unwrap();
+    Pool::create(&conn, Some(1), Some("Animals".to_string())).unwrap();
+    Pool::create(&conn, Some(2), Some("Lonely".to_string())).unwrap();
+    for (front, back) in [("犬", "dog"), ("猫", "cat"), ("鳥", "bird"), ("魚", "fish")] {
+        Card::add(&conn, card(front, back, 1, "Animals")).unwrap();
+    }
+    Card::add(&conn, card("一人", "alone", 2, "Lonely")).unwrap();
+    conn
+}
@@ -0,0 +1,198 @@
+use libfukushuu::db::{self, Card, Category, Note, Pool};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const JSON: &str = r#"{
+    "categories": [{
+        "name": "Animals",
+        "pools": [{
+            "id": 1,
+            "category_name": "Animals",
+            "cards": [{ "front": "犬", "back": "dog" }]
+        }]
+    }]
+}"#;
+
+const NOTES_JSON: &str = r#"{
+    "note_types": [{
+        "name": "Vocabulary",
+        "fields": ["kanji", "meaning"],
+        "templates": [
+            { "name": "meaning", "front": "{{kanji}}", "back": "{{meaning}}" },
+            { "name": "kanji", "front": "{{meaning}}", "back": "{{kanji}}" }
+        ]
+    }],
+    "categories": [{
+        "name": "Animals",
+        "pools": [{
+            "id": 1,
+            "category_name": "Animals",
+            "cards": [{ "front": "犬", "back": "dog" }],
+            "notes": [{ "note_type": "Vocabulary", "fields": { "kanji": "猫", "meaning": "cat" } }]
+        }]
+    }]
+}"#;
+
+/// A fresh directory holding `json` to import, named after the test using it.
+fn workspace(name: &str, json: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fukushuu-import-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("cards.json"), json).unwrap();
+    dir
+}
+
+/// Runs `command` of the importer, with its arguments, on the database in `dir` with the JSON
+/// file `json` in it.
+fn run(dir: &Path, db: &str, json: &str, command: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nyuushutsuryokusha"))
+        .arg("--db")
+        .arg(dir.join(db))
+        .arg(dir.join(json))
+        .args(command)
+        .output()
+        .unwrap()
+}
+
+fn import(dir: &Path) -> Output {
+    run(dir, "flashcards.db", "cards.json", &["import"])
+}
+
+fn assert_refused(dir: &Path, item: &str) {
+    let output = import(dir);
+    let log = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        log.contains(&format!("{item} is in the trash, restore it first")),
+        "{log}"
+    );
+
+    let conn = db::create_or_open(dir.join("flashcards.db")).unwrap();
+    // Nothing was added next to the trashed card.
+    let cards: i64 = conn
+        .query_row("SELECT COUNT(*) FROM Card", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(cards, 1);
+    assert!(Card::get_all(&conn).unwrap().is_empty());
+    db::close_db(conn).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn importing_into_a_trashed_category_fails() {
+    let dir = workspace("category", JSON);
+    assert!(import(&dir).status.success());
+    let conn = db::create_or_open(dir.join("flashcards.db")).unwrap();
+    Category::trash(&conn, "Animals").unwrap();
+    db::close_db(conn).unwrap();
+
+    assert_refused(&dir, "Category 'Animals'");
+}
+
+#[test]
+fn importing_into_a_trashed_pool_fails() {
+    let dir = workspace("pool", JSON);
+    assert!(import(&dir).status.success());
+    let conn = db::create_or_open(dir.join("flashcards.db")).unwrap();
+    Pool::trash(&conn, 1).unwrap();
+    db::close_db(conn).unwrap();
+
+    assert_refused(&dir, "Pool 1");
+}
+
+#[test]
+fn notes_are_exported_instead_of_their_cards() {
+    let dir = workspace("notes", NOTES_JSON);
+    assert!(import(&dir).status.success());
+    assert!(run(&dir, "flashcards.db", "exported.json", &["export"])
+        .status
+        .success());
+
+    let exported: Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("exported.json")).unwrap()).unwrap();
+    assert_eq!(exported["note_types"][0]["name"], "Vocabulary");
+    let pool = &exported["categories"][0]["pools"][0];
+    assert_eq!(pool["cards"].as_array().unwrap().len(), 1);
+    assert_eq!(pool["cards"][0]["front"], "犬");
+    assert_eq!(pool["notes"].as_array().unwrap().len(), 1);
+    assert_eq!(pool["notes"][0]["fields"]["kanji"], "猫");
+
+    // Importing the export elsewhere brings back the same cards, not twice the generated ones.
+    assert!(run(&dir, "copy.db", "exported.json", &["import"])
+        .status
+        .success());
+    let conn = db::create_or_open(dir.join("copy.db")).unwrap();
+    assert_eq!(Card::get_in_pool(&conn, 1).unwrap().len(), 3);
+    assert_eq!(Note::get_in_pool(&conn, 1).unwrap().len(), 1);
+    db::close_db(conn).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn pools_without_an_id_get_a_new_one() {
+    let dir = workspace("new-pool", JSON);
+    assert!(import(&dir).status.success());
+    std::fs::write(
+        dir.join("more.json"),
+        r#"{
+            "categories": [{
+                "name": "Animals",
+                "pools": [{
+                    "category_name": "Animals",
+                    "cards": [{ "front": "猫", "back": "cat" }]
+                }]
+            }]
+        }"#,
+    )
+    .unwrap();
+    assert!(run(&dir, "flashcards.db", "more.json", &["import"])
+        .status
+        .success());
+
+    let conn = db::create_or_open(dir.join("flashcards.db")).unwrap();
+    let pools = Pool::get_all_in_category(&conn, &"Animals".to_string()).unwrap();
+    assert_eq!(pools.iter().map(|pool| pool.id).collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(Card::get_in_pool(&conn, 2).unwrap()[0].back, "cat");
+    db::close_db(conn).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn invalid_cards_fail_the_import_unless_partial() {
+    let dir = workspace(
+        "invalid-card",
+        r#"{
+            "categories": [{
+                "name": "Animals",
+                "pools": [{
+                    "id": 1,
+                    "category_name": "Animals",
+                    "cards": [{ "front": "犬", "back": "dog" }, { "back": "cat" }]
+                }]
+            }]
+        }"#,
+    );
+    let output = import(&dir);
+    let log = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(log.contains("Import failed, nothing was changed"), "{log}");
+    let conn = db::create_or_open(dir.join("flashcards.db")).unwrap();
+    assert!(Card::get_all(&conn).unwrap().is_empty());
+    db::close_db(conn).unwrap();
+
+    assert!(run(
+        &dir,
+        "flashcards.db",
+        "cards.json",
+        &["import", "--partial"]
+    )
+    .status
+    .success());
+    let conn = db::create_or_open(dir.join("flashcards.db")).unwrap();
+    let cards = Card::get_all(&conn).unwrap();
+    assert_eq!(cards.len(), 1);
+    assert_eq!(cards[0].back, "dog");
+    db::close_db(conn).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
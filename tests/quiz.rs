@@ -0,0 +1,451 @@
+mod common;
+
+use common::{card, setup, PROFILE};
+use libfukushuu::audio::{AudioPlayer, NullPlayer};
+use libfukushuu::db::{
+    self, Answer, Card, CardTemplate, Category, Note, NoteType, Pool, Review, Session, SessionMode,
+    TagFilter,
+};
+use libfukushuu::scheduler::SchedulerConfig;
+use libfukushuu::shitsumon::{self, Direction, Image, OptionPair, Selection};
+use libfukushuu::{stats, Error};
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn questions(
+    conn: &Connection,
+    category: &str,
+    choices_count: u32,
+    media_dir: &Path,
+) -> libfukushuu::Result<Vec<shitsumon::Question>> {
+    let category = shitsumon::category(conn, Some(category))?;
+    let cards = shitsumon::get_question_cards(
+        conn,
+        PROFILE,
+        4,
+        Selection::RandomPools(category),
+        &TagFilter::default(),
+    )?;
+    shitsumon::init_questions(
+        conn,
+        PROFILE,
+        cards,
+        choices_count,
+        &SchedulerConfig::default(),
+        None,
+        media_dir,
+    )
+}
+
+fn media_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// Adds a "Vocabulary" note to `pool_id` for each kanji and meaning in `words`.
+fn add_notes(conn: &Connection, pool_id: i32, words: &[(&str, &str)]) {
+    if NoteType::get_one(conn, "Vocabulary").is_err() {
+        let template = |name: &str, front: &str, back: &str| CardTemplate {
+            name: name.to_string(),
+            front: front.to_string(),
+            back: back.to_string(),
+        };
+        let note_type = NoteType {
+            name: "Vocabulary".to_string(),
+            fields: vec!["kanji".to_string(), "meaning".to_string()],
+            templates: vec![
+                template("meaning", "{{kanji}}", "{{meaning}}"),
+                template("kanji", "{{meaning}}", "{{kanji}}"),
+            ],
+        };
+        NoteType::add(conn, &note_type).unwrap();
+    }
+    for (kanji, meaning) in words {
+        let note = Note {
+            id: None,
+            note_type_name: "Vocabulary".to_string(),
+            pool_id,
+            fields: BTreeMap::from([
+                ("kanji".to_string(), kanji.to_string()),
+                ("meaning".to_string(), meaning.to_string()),
+            ]),
+        };
+        Note::add(conn, &note).unwrap();
+    }
+}
+
+/// Asserts that every question has `choices_count` options, one of them correct.
+fn assert_full_options(questions: &[shitsumon::Question], choices_count: usize) {
+    for question in questions {
+        let (options, correct) = question.get_options_randomize();
+        assert_eq!(options.len(), choices_count);
+        assert!(question.is_correct(&options[correct]));
+        assert_eq!(
+            options
+                .iter()
+                .filter(|option| question.is_correct(option))
+                .count(),
+            1
+        );
+    }
+}
+
+#[test]
+fn questions_have_one_correct_option_from_the_pool() {
+    let conn = setup();
+    let questions = questions(&conn, "Animals", 3, &media_dir()).unwrap();
+
+    assert_eq!(questions.len(), 4);
+    for question in &questions {
+        let (options, correct) = question.get_options_randomize();
+        assert_eq!(options.len(), 3);
+        assert!(question.is_correct(&options[correct]));
+        assert_eq!(
+            options
+                .iter()
+                .filter(|option| question.is_correct(option))
+                .count(),
+            1
+        );
+    }
+}
+
+#[test]
+fn english_answers_that_look_like_romaji_are_different_options() {
+    let conn = setup();
+    Category::create(&conn, "Words".to_string(), None, None).unwrap();
+    Pool::create(&conn, Some(3), Some("Words".to_string())).unwrap();
+    for (front, back) in [
+        ("楽しい", "fun"),
+        ("フン族", "hun"),
+        ("座る", "sit"),
+        ("見る", "see"),
+    ] {
+        Card::add(&conn, card(front, back, 3, "Words")).unwrap();
+    }
+
+    for question in questions(&conn, "Words", 4, &media_dir()).unwrap() {
+        let (options, correct) = question.get_options_randomize();
+        assert_eq!(options.len(), 4);
+        assert!(question.is_correct(&options[correct]));
+        assert_eq!(
+            options
+                .iter()
+                .filter(|option| question.is_correct(option))
+                .count(),
+            1
+        );
+    }
+}
+
+#[test]
+fn single_note_pools_borrow_options_from_the_same_template() {
+    let conn = setup();
+    Category::create(&conn, "Words".to_string(), None, None).unwrap();
+    Pool::create(&conn, Some(3), Some("Words".to_string())).unwrap();
+    Pool::create(&conn, Some(4), Some("Words".to_string())).unwrap();
+    add_notes(&conn, 3, &[("水", "water")]);
+    add_notes(
+        &conn,
+        4,
+        &[("火", "fire"), ("木", "tree"), ("山", "mountain")],
+    );
+
+    let questions = questions(&conn, "Words", 4, &media_dir()).unwrap();
+    assert_full_options(&questions, 4);
+    // A meaning is never offered for a kanji, or the other way around.
+    for question in &questions {
+        let (options, _) = question.get_options_randomize();
+        let is_meaning = |option: &OptionPair| option.0.as_ref().unwrap().is_ascii();
+        let asks_meaning = is_meaning(&question.correct_option);
+        assert!(options
+            .iter()
+            .all(|option| is_meaning(option) == asks_meaning));
+    }
+}
+
+#[test]
+fn notes_in_pools_of_plain_cards_fall_back_to_the_plain_cards() {
+    let conn = setup();
+    add_notes(&conn, 1, &[("馬", "horse")]);
+    // The meaning card of the note, and two plain cards.
+    let cards: Vec<Card> = Card::get_in_pool(&conn, 1)
+        .unwrap()
+        .into_iter()
+        .filter(|card| ["馬", "犬", "猫"].contains(&card.front.as_str()))
+        .collect();
+
+    let questions = shitsumon::init_questions(
+        &conn,
+        PROFILE,
+        cards,
+        4,
+        &SchedulerConfig::default(),
+        None,
+        &media_dir(),
+    )
+    .unwrap();
+    assert_eq!(questions.len(), 3);
+    assert_full_options(&questions, 4);
+}
+
+#[test]
+fn answers_update_progress_and_session() {
+    let conn = setup();
+    let mut questions = questions(&conn, "Animals", 4, &media_dir()).unwrap();
+    let session_id = Session::start(
+        &conn,
+        Session {
+            id: None,
+            profile_name: PROFILE.to_string(),
+            started_at: db::now_timestamp(),
+            ended_at: None,
+            category_name: Some("Animals".to_string()),
+            mode: SessionMode::RandomPools,
+            question_count: questions.len() as u32,
+            choices_count: 4,
+            correct: 0,
+            incorrect: 0,
+            dont_know: 0,
+            score_delta: 0,
+        },
+    )
+    .unwrap();
+
+    let right = &mut questions[0];
+    right.session_id = Some(session_id);
+    let correct_option = right.correct_option.clone();
+    let (before, after) = right
+        .record_answer(
+            &conn,
+            Answer::Correct,
+            Some(&correct_option),
+            Duration::from_secs(2),
+        )
+        .unwrap();
+    assert_eq!((before, after), (0, 1));
+    let wrong = &mut questions[1];
+    wrong.session_id = Some(session_id);
+    wrong
+        .record_answer(&conn, Answer::DontKnow, None, Duration::from_secs(5))
+        .unwrap();
+
+    assert_eq!(
+        Card::get_progress(&conn, PROFILE, questions[0].card_id)
+            .unwrap()
+            .score,
+        1
+    );
+    assert_eq!(
+        Review::get_for_card(&conn, questions[1].card_id)
+            .unwrap()
+            .len(),
+        1
+    );
+    let summary = shitsumon::finish_session(&conn, session_id).unwrap();
+    assert_eq!((summary.session.correct, summary.session.dont_know), (1, 1));
+    assert_eq!(summary.accuracy(), 0.5);
+    assert_eq!(summary.missed.len(), 1);
+
+    let stats = stats::compute(&conn, PROFILE, 10).unwrap();
+    assert_eq!(stats.categories[0].answered, 2);
+    assert_eq!(stats.never_answered_count, 3);
+    assert_eq!(stats.current_streak, 1);
+
+    // Answers to cards in the trash are left out of the accuracy.
+    Card::trash(&conn, questions[1].card_id).unwrap();
+    let stats = stats::compute(&conn, PROFILE, 10).unwrap();
+    assert_eq!(stats.categories[0].answered, 1);
+    assert_eq!(stats.pools[0].accuracy, 1.0);
+}
+
+#[test]
+fn problems_are_reported_as_errors() {
+    let conn = setup();
+
+    assert!(matches!(
+        questions(&conn, "Plants", 4, &media_dir()),
+        Err(Error::UnknownCategory(name)) if name == "Plants"
+    ));
+    assert!(matches!(
+        questions(&conn, "Lonely", 4, &media_dir()),
+        Err(Error::PoolTooSmall {
+            pool_id: 2,
+            cards: 1,
+            needed: 4
+        })
+    ));
+
+    Category::create(&conn, "Empty".to_string(), None, None).unwrap();
+    assert!(matches!(
+        questions(&conn, "Empty", 4, &media_dir()),
+        Err(Error::NoPools(_))
+    ));
+
+    let empty = db::open_in_memory().unwrap();
+    assert!(matches!(
+        shitsumon::category(&empty, None),
+        Err(Error::NoCategories)
+    ));
+}
+
+#[test]
+fn relative_images_are_found_in_the_media_dir() {
+    let conn = setup();
+    let id = Card::get_all(&conn).unwrap()[0].id.unwrap();
+    let mut with_image = card("犬", "dog", 1, "Animals");
+    with_image.front_image = PathBuf::from("quiz.rs");
+    Card::update(&conn, id, with_image).unwrap();
+
+    let questions_with_image = questions(&conn, "Animals", 4, &media_dir()).unwrap();
+    let question = questions_with_image
+        .iter()
+        .find(|question| question.card_id == id)
+        .unwrap();
+    assert_eq!(
+        question.front.1,
+        Some(Image::File(media_dir().join("quiz.rs")))
+    );
+
+    // Elsewhere the image is missing, so the card is skipped, unless nothing else is left.
+    let elsewhere = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let questions_without_image = questions(&conn, "Animals", 4, elsewhere).unwrap();
+    assert!(!questions_without_image.is_empty());
+    assert!(questions_without_image
+        .iter()
+        .all(|question| question.card_id != id));
+    assert!(matches!(
+        shitsumon::init_questions(
+            &conn,
+            PROFILE,
+            vec![Card::get_by_id(&conn, id).unwrap()],
+            4,
+            &SchedulerConfig::default(),
+            None,
+            elsewhere,
+        ),
+        Err(Error::MissingImage { card_id, .. }) if card_id == id
+    ));
+}
+
+#[test]
+fn options_with_missing_images_are_left_out() {
+    let conn = setup();
+    let mut cards = Card::get_in_pool(&conn, 1).unwrap();
+    let dog = cards.remove(0).id.unwrap();
+    let mut with_image = card("犬", "dog", 1, "Animals");
+    with_image.front_image = PathBuf::from("missing.png");
+    Card::update(&conn, dog, with_image).unwrap();
+
+    let questions = shitsumon::init_questions(
+        &conn,
+        PROFILE,
+        cards,
+        4,
+        &SchedulerConfig::default(),
+        None,
+        &media_dir(),
+    )
+    .unwrap();
+    assert_eq!(questions.len(), 3);
+    assert_full_options(&questions, 3);
+}
+
+#[test]
+fn small_pools_get_fewer_options() {
+    let conn = setup();
+    Card::add(&conn, card("二人", "two people", 2, "Lonely")).unwrap();
+
+    let questions = questions(&conn, "Lonely", 4, &media_dir()).unwrap();
+    assert_full_options(&questions, 2);
+}
+
+#[test]
+fn sounds_are_resolved_against_the_media_dir() {
+    let conn = setup();
+    let id = Card::get_all(&conn).unwrap()[0].id.unwrap();
+    let mut with_sound = card("犬", "dog", 1, "Animals");
+    with_sound.front_audio = PathBuf::from("inu.ogg");
+    Card::update(&conn, id, with_sound).unwrap();
+
+    let questions = questions(&conn, "Animals", 4, &media_dir()).unwrap();
+    let question = questions
+        .iter()
+        .find(|question| question.card_id == id)
+        .unwrap();
+    assert_eq!(question.front_audio, Some(media_dir().join("inu.ogg")));
+    assert_eq!(question.back_audio, None);
+
+    let player = NullPlayer::default();
+    player.play(question.front_audio.as_ref().unwrap()).unwrap();
+    assert_eq!(player.played(), vec![media_dir().join("inu.ogg")]);
+}
+
+#[test]
+fn details_are_kept_for_after_answering() {
+    let conn = setup();
+    let id = Card::get_all(&conn).unwrap()[0].id.unwrap();
+    let mut with_details = card("犬", "dog", 1, "Animals");
+    with_details.reading = "いぬ".to_string();
+    with_details.example = "犬が好きです。".to_string();
+    Card::update(&conn, id, with_details).unwrap();
+
+    let questions = questions(&conn, "Animals", 4, &media_dir()).unwrap();
+    let question = questions
+        .iter()
+        .find(|question| question.card_id == id)
+        .unwrap();
+    assert_eq!(question.reading.as_deref(), Some("いぬ"));
+    assert_eq!(question.notes, None);
+    assert_eq!(question.example.as_deref(), Some("犬が好きです。"));
+}
+
+#[test]
+fn reversed_questions_ask_for_the_front() {
+    let conn = setup();
+    let fronts: Vec<String> = Card::get_all(&conn)
+        .unwrap()
+        .into_iter()
+        .filter(|card| card.category_name.as_deref() == Some("Animals"))
+        .map(|card| card.front)
+        .collect();
+    Category::set_direction(&conn, "Animals", Some(Direction::Reverse)).unwrap();
+    assert_eq!(
+        Category::get_one(&conn, "Animals").unwrap().direction,
+        Some(Direction::Reverse)
+    );
+
+    for question in questions(&conn, "Animals", 4, &media_dir()).unwrap() {
+        assert!(question.reversed);
+        let (options, _) = question.get_options_randomize();
+        for OptionPair(text, _) in options {
+            assert!(fronts.contains(&text.unwrap()));
+        }
+    }
+}
+
+#[test]
+fn trashed_cards_are_not_asked() {
+    let conn = setup();
+    let dog = Card::get_all(&conn).unwrap()[0].id.unwrap();
+    Card::trash(&conn, dog).unwrap();
+
+    let asked = questions(&conn, "Animals", 3, &media_dir()).unwrap();
+    for question in &asked {
+        assert_ne!(question.card_id, dog);
+        let (options, _) = question.get_options_randomize();
+        assert!(!options.contains(&OptionPair(Some("dog".to_string()), None)));
+    }
+
+    Pool::trash(&conn, 1).unwrap();
+    assert!(matches!(
+        questions(&conn, "Animals", 3, &media_dir()),
+        Err(Error::NoPools(_))
+    ));
+    Category::trash(&conn, "Animals").unwrap();
+    assert!(matches!(
+        questions(&conn, "Animals", 3, &media_dir()),
+        Err(Error::UnknownCategory(_))
+    ));
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::{setup, PROFILE};
+use libfukushuu::db::{Answer, Card, Category, TagFilter};
+use libfukushuu::scheduler::SchedulerConfig;
+use libfukushuu::shitsumon::Selection;
+use libfukushuu::worker::{Request, Response, Worker};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn worker_answers_requests_in_order() {
+    let conn = setup();
+    let category = Category::get_one(&conn, "Animals").unwrap();
+    let notified = Arc::new(AtomicUsize::new(0));
+    let counter = notified.clone();
+    let worker = Worker::spawn(conn, move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+
+    assert!(worker.send(Request::LoadQuestions {
+        profile_name: PROFILE.to_string(),
+        question_count: 4,
+        choices_count: 4,
+        selection: Selection::RandomPools(category),
+        tags: TagFilter::default(),
+        scheduler_config: SchedulerConfig::default(),
+        direction: None,
+        media_dir: PathBuf::new(),
+    }));
+    let questions = match worker.recv() {
+        Some(Response::QuestionsLoaded(Ok(questions))) => questions,
+        other => panic!("expected questions, got {other:?}"),
+    };
+    assert_eq!(questions.len(), 4);
+
+    let question = questions[0].clone();
+    let card_id = question.card_id;
+    worker.send(Request::RecordAnswer {
+        chosen_option: Some(question.correct_option.clone()),
+        question,
+        answer: Answer::Correct,
+        response_time: Duration::from_secs(1),
+    });
+    worker.send(Request::Stats {
+        profile_name: PROFILE.to_string(),
+        limit: 10,
+    });
+    assert!(matches!(
+        worker.recv(),
+        Some(Response::AnswerRecorded { card_id: id, result: Ok((0, 1)) }) if id == card_id
+    ));
+    match worker.recv() {
+        Some(Response::Stats(Ok(stats))) => assert_eq!(stats.never_answered_count, 4),
+        other => panic!("expected stats, got {other:?}"),
+    }
+    assert!(worker.try_recv().is_none());
+
+    let conn = worker.shutdown().unwrap();
+    assert_eq!(notified.load(Ordering::SeqCst), 3);
+    assert_eq!(
+        Card::get_progress(&conn, PROFILE, card_id).unwrap().score,
+        1
+    );
+}